@@ -0,0 +1,179 @@
+----------------------------- MODULE PaxosCommit ----------------------------
+(*
+This module implements the Paxos Commit specification presented in the paper
+"Consensus on Transaction Commit" by Jim Gray and Leslie Lamport. It has been
+adapted only slightly for the book: rather than instantiating the paper's
+TCommit module, it checks the same Consistent invariant as TwoPhase.tla.
+
+The paper can be found here:
+https://www.microsoft.com/en-us/research/wp-content/uploads/2016/02/tr-2003-96.pdf
+
+From that paper:
+
+> This paper appeared in _ACM Transactions on Database Systems_, Volume 31,
+> Issue 1, March 2006 (pages 133-160).  This version should differ from the
+> published one only in formatting, except that it corrects one minor error on
+> the last page.
+>
+> Copyright 2005 by the Association for Computing Machinery, Inc.  Permission
+> to make digital or hard copies of part or all of this work for personal or
+> classroom use is granted without fee provided that copies are not made or
+> distributed for profit or  commercial  advantage and  that  copies  bear this
+> notice  and  the  full  citationon the first page.  Copyrights for components
+> of this work owned by others than ACM must be honored.  Abstracting with
+> credit is permitted.  To copy otherwise, to republish, to post on servers, or
+> to redistribute to lists, requires prior specificpermission and/or a fee.
+> Request permissions from Publications Dept, ACM Inc.,fax +1 (212) 869-0481,
+> or permissions@acm.org.
+*)
+EXTENDS Integers
+
+(* ANCHOR: constants *)
+CONSTANT RM, Acceptor, Majority, Ballot
+
+ASSUME
+  /\ Ballot \subseteq Nat
+  /\ 0 \in Ballot
+  /\ Majority \subseteq SUBSET Acceptor
+  /\ \A MS1, MS2 \in Majority : MS1 \cap MS2 # {}
+(* ANCHOR_END: constants *)
+
+(* ANCHOR: variables *)
+VARIABLES
+  rmState,
+  aState,
+  msgs
+(* ANCHOR_END: variables *)
+
+(* ANCHOR: types *)
+Message ==
+       [type : {"phase1a"}, ins : RM, bal : Ballot \ {0}]
+  \cup [type : {"phase1b"}, ins : RM, mbal : Ballot, bal : Ballot \cup {-1},
+        val : {"prepared", "aborted", "none"}, acc : Acceptor]
+  \cup [type : {"phase2a"}, ins : RM, bal : Ballot, val : {"prepared", "aborted"}]
+  \cup [type : {"phase2b"}, acc : Acceptor, ins : RM, bal : Ballot,
+        val : {"prepared", "aborted"}]
+  \cup [type : {"Commit", "Abort"}]
+
+PCTypeOK ==
+  /\ rmState \in [RM -> {"working", "prepared", "committed", "aborted"}]
+  /\ aState  \in [RM -> [Acceptor -> [mbal : Ballot,
+                                      bal  : Ballot \cup {-1},
+                                      val  : {"prepared", "aborted", "none"}]]]
+  /\ msgs \subseteq Message
+(* ANCHOR_END: types *)
+
+(* ANCHOR: init *)
+PCInit ==
+  /\ rmState = [rm \in RM |-> "working"]
+  /\ aState  = [ins \in RM |->
+                 [ac \in Acceptor
+                    |-> [mbal |-> 0, bal  |-> -1, val  |-> "none"]]]
+  /\ msgs = {}
+(* ANCHOR_END: init *)
+
+(* ANCHOR: next *)
+Send(m) == msgs' = msgs \cup {m}
+
+Maximum(S) ==
+  IF S = {} THEN -1
+            ELSE CHOOSE n \in S : \A m \in S : n \geq m
+
+RMPrepare(rm) ==
+  /\ rmState[rm] = "working"
+  /\ rmState' = [rmState EXCEPT ![rm] = "prepared"]
+  /\ Send([type |-> "phase2a", ins |-> rm, bal |-> 0, val |-> "prepared"])
+  /\ UNCHANGED aState
+
+RMChooseToAbort(rm) ==
+  /\ rmState[rm] = "working"
+  /\ rmState' = [rmState EXCEPT ![rm] = "aborted"]
+  /\ Send([type |-> "phase2a", ins |-> rm, bal |-> 0, val |-> "aborted"])
+  /\ UNCHANGED aState
+
+RMRcvCommitMsg(rm) ==
+  /\ [type |-> "Commit"] \in msgs
+  /\ rmState' = [rmState EXCEPT ![rm] = "committed"]
+  /\ UNCHANGED <<aState, msgs>>
+
+RMRcvAbortMsg(rm) ==
+  /\ [type |-> "Abort"] \in msgs
+  /\ rmState' = [rmState EXCEPT ![rm] = "aborted"]
+  /\ UNCHANGED <<aState, msgs>>
+
+Phase1a(bal, rm) ==
+  /\ Send([type |-> "phase1a", ins |-> rm, bal |-> bal])
+  /\ UNCHANGED <<rmState, aState>>
+
+Phase2a(bal, rm) ==
+  /\ ~\E m \in msgs : /\ m.type = "phase2a"
+                      /\ m.bal = bal
+                      /\ m.ins = rm
+  /\ \E MS \in Majority :
+        LET mset == {m \in msgs : /\ m.type = "phase1b"
+                                  /\ m.ins  = rm
+                                  /\ m.mbal = bal
+                                  /\ m.acc  \in MS}
+            maxbal == Maximum({m.bal : m \in mset})
+            val == IF maxbal = -1
+                     THEN "aborted"
+                     ELSE (CHOOSE m \in mset : m.bal = maxbal).val
+        IN  /\ \A ac \in MS : \E m \in mset : m.acc = ac
+            /\ Send([type |-> "phase2a", ins |-> rm, bal |-> bal, val |-> val])
+  /\ UNCHANGED <<rmState, aState>>
+
+PCDecide ==
+  /\ LET Decided(rm, v) ==
+           \E b \in Ballot, MS \in Majority :
+             \A ac \in MS : [type |-> "phase2b", ins |-> rm,
+                              bal |-> b, val |-> v, acc |-> ac ] \in msgs
+     IN  \/ /\ \A rm \in RM : Decided(rm, "prepared")
+            /\ Send([type |-> "Commit"])
+         \/ /\ \E rm \in RM : Decided(rm, "aborted")
+            /\ Send([type |-> "Abort"])
+  /\ UNCHANGED <<rmState, aState>>
+
+Phase1b(acc) ==
+  \E m \in msgs :
+    /\ m.type = "phase1a"
+    /\ aState[m.ins][acc].mbal < m.bal
+    /\ aState' = [aState EXCEPT ![m.ins][acc].mbal = m.bal]
+    /\ Send([type |-> "phase1b",
+             ins  |-> m.ins,
+             mbal |-> m.bal,
+             bal  |-> aState[m.ins][acc].bal,
+             val  |-> aState[m.ins][acc].val,
+             acc  |-> acc])
+    /\ UNCHANGED rmState
+
+Phase2b(acc) ==
+  /\ \E m \in msgs :
+       /\ m.type = "phase2a"
+       /\ aState[m.ins][acc].mbal \leq m.bal
+       /\ aState' = [aState EXCEPT ![m.ins][acc].mbal = m.bal,
+                                   ![m.ins][acc].bal  = m.bal,
+                                   ![m.ins][acc].val  = m.val]
+       /\ Send([type |-> "phase2b", ins |-> m.ins, bal |-> m.bal,
+                val |-> m.val, acc |-> acc])
+  /\ UNCHANGED rmState
+
+PCNext ==
+  \/ \E rm \in RM : \/ RMPrepare(rm)
+                    \/ RMChooseToAbort(rm)
+                    \/ RMRcvCommitMsg(rm)
+                    \/ RMRcvAbortMsg(rm)
+  \/ \E bal \in Ballot \ {0}, rm \in RM : Phase1a(bal, rm) \/ Phase2a(bal, rm)
+  \/ PCDecide
+  \/ \E acc \in Acceptor : Phase1b(acc) \/ Phase2b(acc)
+(* ANCHOR_END: next *)
+
+(* ANCHOR: spec *)
+PCSpec == PCInit /\ [][PCNext]_<<rmState, aState, msgs>>
+(* ANCHOR_END: spec *)
+
+(* ANCHOR: properties *)
+Consistent ==
+  \A rm1, rm2 \in RM : ~ /\ rmState[rm1] = "aborted"
+                         /\ rmState[rm2] = "committed"
+(* ANCHOR_END: properties *)
+=============================================================================
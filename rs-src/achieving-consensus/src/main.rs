@@ -0,0 +1,335 @@
+//! Provides a linearizable register "shared memory" abstraction by having a cluster of actors
+//! agree upon a sequence of commands (a replicated log). The actors reach consensus on each entry
+//! of the log via the Multi-Paxos algorithm, which is described in "[Paxos Made
+//! Simple](https://lamport.azurewebsites.net/pubs/paxos-simple.pdf)" by Leslie Lamport.
+//!
+//! For a succinct overview of the algorithm, I recommend:
+//! https://www.cs.cornell.edu/courses/cs7412/2011sp/paxos.pdf
+
+/* ANCHOR: all */
+use serde::{Deserialize, Serialize};
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{SocketAddrV4, Ipv4Addr};
+
+// ANCHOR: actor-msg
+type RequestId = u64;
+type Value = char;
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+enum PaxosMsg {
+    Prepare { ballot: Ballot },
+    Prepared { ballot: Ballot, accepted: Log },
+    Accept { ballot: Ballot, slot: Slot, command: Command },
+    Accepted { ballot: Ballot, slot: Slot },
+    Decided { ballot: Ballot, slot: Slot, command: Command },
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+enum Command {
+    Noop, // fills gaps in the log
+    Get(Id, RequestId),
+    Put(Id, RequestId, Value),
+}
+// ANCHOR_END: actor-msg
+use PaxosMsg::*;
+
+// ANCHOR: actor-state
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct PaxosState {
+    // shared state
+    ballot: Ballot,
+
+    // acceptor state
+    accepted: Log,
+    decided: BTreeMap<Slot, Command>,
+
+    // leader state
+    is_leader: bool,
+    prepares: BTreeMap<Id, Log>,
+    accepts: BTreeMap<Slot, BTreeSet<Id>>,
+    proposal: Option<Command>,
+    proposal_slot: Option<Slot>,
+}
+
+type Ballot = (Round, Id); // `Id` for uniqueness
+type Round = u32;
+type Slot = usize;
+type Log = BTreeMap<Slot, (Ballot, Command)>;
+// ANCHOR_END: actor-state
+
+// ANCHOR: actor
+#[derive(Clone)]
+struct PaxosActor {
+    peers: Vec<Id>,
+}
+
+impl Actor for PaxosActor {
+    type Msg = RegisterMsg<RequestId, Value, PaxosMsg>;
+    type State = PaxosState;
+    type Timer = ();
+
+    fn on_start(&self, _id: Id, _o: &mut Out<Self>) -> Self::State {
+        PaxosState {
+            ballot: (0, Id::from(0)),
+            accepted: Default::default(),
+            decided: Default::default(),
+            is_leader: false,
+            prepares: Default::default(),
+            accepts: Default::default(),
+            proposal: None,
+            proposal_slot: None,
+        }
+    }
+
+    fn on_msg(&self, id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        use RegisterMsg::*;
+        match msg {
+            Put(req_id, _) | Get(req_id) if state.proposal.is_none() => {
+                let state = state.to_mut();
+                let command = if let Put(_, val) = msg {
+                    Command::Put(src, req_id, val)
+                } else {
+                    Command::Get(src, req_id)
+                };
+                state.proposal = Some(command);
+
+                if state.is_leader {
+                    // Already leading, so skip phase 1 and propose the next slot.
+                    let ballot = state.ballot;
+                    let slot = state.accepted.keys().next_back().map_or(0, |s| s + 1);
+                    o.broadcast(
+                        &self.peers,
+                        &Internal(Accept { ballot, slot, command }));
+
+                    // Self-send `Accept` and `Accepted`.
+                    state.accepted.insert(slot, (ballot, command));
+                    state.accepts.insert(slot, std::iter::once(id).collect());
+                    state.proposal_slot = Some(slot);
+                } else {
+                    state.ballot = (state.ballot.0 + 1, id);
+                    o.broadcast(
+                        &self.peers,
+                        &Internal(Prepare { ballot: state.ballot }));
+
+                    // Self-send `Prepare` and `Prepared`.
+                    state.prepares = Default::default();
+                    state.prepares.insert(id, state.accepted.clone());
+                }
+            }
+            Internal(Prepare { ballot }) if state.ballot < ballot => {
+                let state = state.to_mut();
+                // A newer leader exists, so drop the request being coordinated (if any).
+                state.is_leader = false;
+                state.prepares = Default::default();
+                state.accepts = Default::default();
+                state.proposal = None;
+                state.proposal_slot = None;
+                state.ballot = ballot;
+                o.send(src, Internal(Prepared {
+                    ballot,
+                    accepted: state.accepted.clone(),
+                }));
+            }
+            Internal(Prepared { ballot, accepted })
+                if ballot == state.ballot && !state.is_leader =>
+            {
+                let state = state.to_mut();
+                state.prepares.insert(src, accepted);
+                if state.prepares.len() == majority(self.peers.len() + 1) {
+                    // Quorum reached. This actor is now the leader, but it first needs to
+                    // drive every entry that may have been decided by a previous leader to
+                    // a quorum at the new ballot. Gaps in the log are filled with `Noop`.
+                    let mut merged = Log::default();
+                    for log in std::mem::take(&mut state.prepares).into_values() {
+                        for (slot, (b, command)) in log {
+                            if merged.get(&slot).is_none_or(|(max_b, _)| *max_b < b) {
+                                merged.insert(slot, (b, command));
+                            }
+                        }
+                    }
+                    let next_slot = merged.keys().next_back().map_or(0, |s| s + 1);
+                    let mut proposals: Vec<_> = (0..next_slot)
+                        .filter(|slot| !state.decided.contains_key(slot))
+                        .map(|slot| {
+                            let command = merged.get(&slot)
+                                .map_or(Command::Noop, |(_, command)| *command);
+                            (slot, command)
+                        })
+                        .collect();
+                    if let Some(command) = state.proposal {
+                        proposals.push((next_slot, command));
+                        state.proposal_slot = Some(next_slot);
+                    }
+
+                    for (slot, command) in proposals {
+                        o.broadcast(
+                            &self.peers,
+                            &Internal(Accept { ballot, slot, command }));
+
+                        // Self-send `Accept` and `Accepted`.
+                        state.accepted.insert(slot, (ballot, command));
+                        state.accepts.insert(slot, std::iter::once(id).collect());
+                    }
+                    state.is_leader = true;
+                }
+            }
+            Internal(Accept { ballot, slot, command }) if state.ballot <= ballot => {
+                let state = state.to_mut();
+                if state.ballot < ballot {
+                    // A newer leader exists, so drop the request being coordinated (if any).
+                    state.is_leader = false;
+                    state.prepares = Default::default();
+                    state.accepts = Default::default();
+                    state.proposal = None;
+                    state.proposal_slot = None;
+                    state.ballot = ballot;
+                }
+                state.accepted.insert(slot, (ballot, command));
+                o.send(src, Internal(Accepted { ballot, slot }));
+            }
+            Internal(Accepted { ballot, slot })
+                if ballot == state.ballot
+                    && matches!(state.accepts.get(&slot),
+                                Some(acks) if !acks.contains(&src)) =>
+            {
+                let state = state.to_mut();
+                let acks = state.accepts.get_mut(&slot).unwrap();
+                acks.insert(src);
+                if acks.len() < majority(self.peers.len() + 1) { return }
+
+                // Quorum reached, so the slot is decided.
+                let (_, command) = state.accepted[&slot];
+                o.broadcast(&self.peers, &Internal(Decided { ballot, slot, command }));
+                state.accepts.remove(&slot);
+                state.decided.insert(slot, command);
+
+                // Reply once the log is decided through the proposal's slot.
+                if let Some(proposal_slot) = state.proposal_slot {
+                    if (0..=proposal_slot).all(|s| state.decided.contains_key(&s)) {
+                        // Replay the log to find the value as of the proposal's slot.
+                        let val = state.decided.range(..proposal_slot)
+                            .rev()
+                            .find_map(|(_, command)| match command {
+                                Command::Put(_, _, val) => Some(*val),
+                                _ => None,
+                            })
+                            .unwrap_or('?');
+                        match state.proposal {
+                            Some(Command::Put(requester, req_id, _)) => {
+                                o.send(requester, PutOk(req_id));
+                            }
+                            Some(Command::Get(requester, req_id)) => {
+                                o.send(requester, GetOk(req_id, val));
+                            }
+                            _ => unreachable!("proposal expected"),
+                        }
+                        state.proposal = None;
+                        state.proposal_slot = None;
+                    }
+                }
+            }
+            Internal(Decided { ballot, slot, command })
+                if !state.decided.contains_key(&slot) =>
+            {
+                let state = state.to_mut();
+                if state.accepted.get(&slot).is_none_or(|(b, _)| *b < ballot) {
+                    state.accepted.insert(slot, (ballot, command));
+                }
+                state.decided.insert(slot, command);
+            }
+            _ => {}
+        }
+    }
+}
+// ANCHOR_END: actor
+
+fn main() {
+    env_logger::init_from_env(
+        env_logger::Env::default().default_filter_or("info"));
+    let id0 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000));
+    let id1 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3001));
+    let id2 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3002));
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (id0, PaxosActor { peers: vec![id1, id2] }),
+            (id1, PaxosActor { peers: vec![id0, id2] }),
+            (id2, PaxosActor { peers: vec![id0, id1] }),
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+
+    // ANCHOR: test
+    #[test]
+    fn is_linearizable_quick() {
+        let checker = base_model()
+            .actor(RegisterActor::Server(PaxosActor {
+                peers: Id::vec_from(vec![1]),
+            }))
+            .actor(RegisterActor::Server(PaxosActor {
+                peers: Id::vec_from(vec![0]),
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 54_167);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "enabled for --release only")]
+    fn is_linearizable() {
+        let checker = base_model()
+            .actor(RegisterActor::Server(PaxosActor {
+                peers: Id::vec_from(vec![1, 2]),
+            }))
+            .actor(RegisterActor::Server(PaxosActor {
+                peers: Id::vec_from(vec![0, 2]),
+            }))
+            .actor(RegisterActor::Server(PaxosActor {
+                peers: Id::vec_from(vec![0, 1]),
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 3 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 3 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+    }
+
+    fn base_model()
+        -> ActorModel<
+            RegisterActor<PaxosActor>,
+            (),
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        ActorModel::new(
+                (),
+                LinearizabilityTester::new(Register('?'))
+            )
+            .init_network(Network::new_unordered_nonduplicating([]))
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.serialized_history().is_some()
+            })
+            .property(Expectation::Sometimes, "value chosen", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    if let RegisterMsg::GetOk(_, value) = e.msg {
+                        return *value != '?';
+                    }
+                    false
+                })
+            })
+            .record_msg_in(RegisterMsg::record_returns)
+            .record_msg_out(RegisterMsg::record_invocations)
+    }
+    // ANCHOR_END: test
+}
+/* ANCHOR_END: all */
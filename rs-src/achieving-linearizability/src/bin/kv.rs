@@ -0,0 +1,306 @@
+//! Provides a linearizable key-value store by running an independent instance of the ABD register
+//! (see `lib.rs`) for each key. Each key has its own sequencer and value, so requests for different
+//! keys proceed concurrently.
+//!
+//! Linearizability is a local property (Herlihy and Wing), so the store is linearizable if the
+//! history of each key is linearizable, which is what the tests check.
+
+use achieving_linearizability::{AbdActor, AbdMsg, AbdState, RequestId, WriteBack};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::net::{SocketAddrV4, Ipv4Addr};
+
+type Key = String;
+
+/// Addresses a message to the register for a key, so `Put` and `Get` carry the key.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+struct KvMsg<Value>(Key, RegisterMsg<RequestId, Value, AbdMsg<Value>>);
+
+type KvState<Value> = BTreeMap<Key, AbdState<Value>>; // absent until a key's register changes
+
+#[derive(Clone)]
+struct KvActor<Value> {
+    register: AbdActor<Value>, // run independently for each key
+}
+
+impl<Value> Actor for KvActor<Value>
+where Value: Clone + Debug + Eq + Hash + Serialize + DeserializeOwned,
+{
+    type Msg = KvMsg<Value>;
+    type State = KvState<Value>;
+    type Timer = ();
+
+    fn on_start(&self, _id: Id, _o: &mut Out<Self>) -> Self::State {
+        Default::default()
+    }
+
+    fn on_msg(&self, id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        let KvMsg(key, msg) = msg;
+        let initial;
+        let register = match state.get(&key) {
+            Some(register) => register,
+            None => {
+                initial = self.register.on_start(id, &mut Out::new());
+                &initial
+            }
+        };
+        let mut register = Cow::Borrowed(register);
+        let mut register_out = Out::new();
+        self.register.on_msg(id, &mut register, src, msg, &mut register_out);
+        if let Cow::Owned(register) = register {
+            state.to_mut().insert(key.clone(), register);
+        }
+        for command in register_out {
+            // The register does not use timers.
+            if let Command::Send(dst, msg) = command {
+                o.send(dst, KvMsg(key.clone(), msg));
+            }
+        }
+    }
+}
+
+fn main() {
+    env_logger::init_from_env(
+        env_logger::Env::default().default_filter_or("info"));
+    let id0 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000));
+    let id1 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3001));
+    let id2 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3002));
+    let write_back = match std::env::args().nth(1).as_deref() {
+        Some("unless-quorum-agrees") => WriteBack::UnlessQuorumAgrees,
+        Some("targeted") => WriteBack::Targeted,
+        _ => WriteBack::Always,
+    };
+    let kv_actor = |peers| KvActor {
+        register: AbdActor { peers, write_back, default_value: String::new() },
+    };
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (id0, kv_actor(vec![id1, id2])),
+            (id1, kv_actor(vec![id0, id2])),
+            (id2, kv_actor(vec![id0, id1])),
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+    use RegisterMsg::*;
+
+    #[test]
+    fn is_linearizable_quick() {
+        // Both writes arrive at server 0, so their phases may run concurrently.
+        let checker = base_model()
+            .actor(TestActor::Server(kv_actor(Id::vec_from(vec![1]))))
+            .actor(TestActor::Server(kv_actor(Id::vec_from(vec![0]))))
+            .actor(TestActor::Client(vec![
+                (Id::from(0), ClientOp::Put("x", 'A')),
+                (Id::from(1), ClientOp::Get("y")),
+            ]))
+            .actor(TestActor::Client(vec![
+                (Id::from(0), ClientOp::Put("y", 'B')),
+                (Id::from(1), ClientOp::Get("x")),
+            ]))
+            .property(Expectation::Sometimes, "concurrent phases", |_, state| {
+                state.actor_states.iter().any(|s| {
+                    matches!(&**s, TestActorState::Server(s)
+                             if s.values().filter(|r| r.is_coordinating()).count() > 1)
+                })
+            })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 354);
+    }
+
+    #[test]
+    fn is_linearizable_quick_with_shared_key() {
+        let checker = base_model()
+            .actor(TestActor::Server(kv_actor(Id::vec_from(vec![1]))))
+            .actor(TestActor::Server(kv_actor(Id::vec_from(vec![0]))))
+            .actor(TestActor::Client(vec![
+                (Id::from(0), ClientOp::Put("x", 'A')),
+                (Id::from(1), ClientOp::Get("x")),
+            ]))
+            .actor(TestActor::Client(vec![
+                (Id::from(1), ClientOp::Put("x", 'B')),
+                (Id::from(0), ClientOp::Get("x")),
+            ]))
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 793);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "enabled for --release only")]
+    fn is_linearizable() {
+        let checker = base_model()
+            .actor(TestActor::Server(kv_actor(Id::vec_from(vec![1, 2]))))
+            .actor(TestActor::Server(kv_actor(Id::vec_from(vec![0, 2]))))
+            .actor(TestActor::Server(kv_actor(Id::vec_from(vec![0, 1]))))
+            .actor(TestActor::Client(vec![
+                (Id::from(0), ClientOp::Put("x", 'A')),
+                (Id::from(1), ClientOp::Get("y")),
+            ]))
+            .actor(TestActor::Client(vec![
+                (Id::from(0), ClientOp::Put("y", 'B')),
+                (Id::from(2), ClientOp::Get("x")),
+            ]))
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 9_931_321);
+    }
+
+    /// Wraps a `KvActor` so that it can be checked alongside clients, much like `RegisterActor`
+    /// but with keys.
+    #[derive(Clone)]
+    enum TestActor {
+        /// Sends each operation to the paired server, awaiting a reply before sending the next.
+        Client(Vec<(Id, ClientOp)>),
+        Server(KvActor<char>),
+    }
+
+    #[derive(Clone)]
+    enum ClientOp {
+        Put(&'static str, char),
+        Get(&'static str),
+    }
+
+    #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+    enum TestActorState {
+        Client { op_count: usize },
+        Server(KvState<char>),
+    }
+
+    impl TestActor {
+        fn send_op(id: Id, ops: &[(Id, ClientOp)], op_count: usize, o: &mut Out<Self>) {
+            if let Some((dst, op)) = ops.get(op_count) {
+                let req_id = request_id(id, op_count);
+                o.send(*dst, match op {
+                    ClientOp::Put(key, val) => KvMsg(key.to_string(), Put(req_id, *val)),
+                    ClientOp::Get(key) => KvMsg(key.to_string(), Get(req_id)),
+                });
+            }
+        }
+    }
+
+    impl Actor for TestActor {
+        type Msg = KvMsg<char>;
+        type State = TestActorState;
+        type Timer = ();
+
+        fn on_start(&self, id: Id, o: &mut Out<Self>) -> Self::State {
+            match self {
+                TestActor::Client(ops) => {
+                    TestActor::send_op(id, ops, 0, o);
+                    TestActorState::Client { op_count: 0 }
+                }
+                TestActor::Server(server) => {
+                    let mut server_out = Out::new();
+                    let state = TestActorState::Server(server.on_start(id, &mut server_out));
+                    o.append(&mut server_out);
+                    state
+                }
+            }
+        }
+
+        fn on_msg(&self, id: Id, state: &mut Cow<Self::State>,
+                  src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+            match (self, &**state) {
+                (TestActor::Client(ops), TestActorState::Client { op_count }) => {
+                    if let KvMsg(_, PutOk(req_id) | GetOk(req_id, _)) = msg {
+                        if req_id != request_id(id, *op_count) { return }
+                        TestActor::send_op(id, ops, op_count + 1, o);
+                        *state = Cow::Owned(TestActorState::Client { op_count: op_count + 1 });
+                    }
+                }
+                (TestActor::Server(server), TestActorState::Server(server_state)) => {
+                    let mut server_state = Cow::Borrowed(server_state);
+                    let mut server_out = Out::new();
+                    server.on_msg(id, &mut server_state, src, msg, &mut server_out);
+                    if let Cow::Owned(server_state) = server_state {
+                        *state = Cow::Owned(TestActorState::Server(server_state));
+                    }
+                    o.append(&mut server_out);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn kv_actor(peers: Vec<Id>) -> KvActor<char> {
+        KvActor {
+            register: AbdActor { peers, write_back: WriteBack::Always, default_value: '?' },
+        }
+    }
+
+    /// Derives a request ID that is unique across clients.
+    fn request_id(client_id: Id, op_count: usize) -> RequestId {
+        (usize::from(client_id) * 1_000 + op_count) as RequestId
+    }
+
+    /// Checks the history of each key independently.
+    #[derive(Clone, Debug, Default, Hash, PartialEq)]
+    struct KvHistory {
+        testers: BTreeMap<Key, LinearizabilityTester<Id, Register<char>>>,
+        in_flight: BTreeMap<Id, Key>,
+    }
+
+    impl KvHistory {
+        fn record_invocations(_cfg: &(), history: &Self, env: Envelope<&KvMsg<char>>)
+            -> Option<Self>
+        {
+            let (key, op) = match env.msg {
+                KvMsg(key, Put(_, val)) => (key, RegisterOp::Write(*val)),
+                KvMsg(key, Get(_)) => (key, RegisterOp::Read),
+                _ => return None,
+            };
+            let mut history = history.clone();
+            let _ = history.testers.entry(key.clone())
+                .or_insert_with(|| LinearizabilityTester::new(Register('?')))
+                .on_invoke(env.src, op);
+            history.in_flight.insert(env.src, key.clone());
+            Some(history)
+        }
+
+        fn record_returns(_cfg: &(), history: &Self, env: Envelope<&KvMsg<char>>)
+            -> Option<Self>
+        {
+            let ret = match env.msg {
+                KvMsg(_, PutOk(_)) => RegisterRet::WriteOk,
+                KvMsg(_, GetOk(_, val)) => RegisterRet::ReadOk(*val),
+                _ => return None,
+            };
+            let mut history = history.clone();
+            let key = history.in_flight.remove(&env.dst)?;
+            let _ = history.testers.get_mut(&key)?.on_return(env.dst, ret);
+            Some(history)
+        }
+    }
+
+    fn base_model() -> ActorModel<TestActor, (), KvHistory> {
+        ActorModel::new((), KvHistory::default())
+            .init_network(Network::new_unordered_nonduplicating([]))
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.testers.values()
+                    .all(|tester| tester.serialized_history().is_some())
+            })
+            .property(Expectation::Sometimes, "value chosen", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    if let KvMsg(_, GetOk(_, value)) = e.msg {
+                        return *value != '?';
+                    }
+                    false
+                })
+            })
+            .record_msg_in(KvHistory::record_returns)
+            .record_msg_out(KvHistory::record_invocations)
+    }
+}
@@ -0,0 +1,580 @@
+//! Provides a linearizable register "shared memory" abstraction that can serve requests as long as
+//! a quorum of actors is available  (e.g. 3 of 5). This code is based on the algorithm described
+//! in "[Sharing Memory Robustly in Message-Passing
+//! Systems](https://doi.org/10.1145/200836.200869)" by Attiya, Bar-Noy, and Dolev. "ABD" in the
+//! types refers to the author names.
+//!
+//! For a succinct overview of the algorithm, I recommend:
+//! http://muratbuffalo.blogspot.com/2012/05/replicatedfault-tolerant-atomic-storage.html
+
+/* ANCHOR: all */
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+// ANCHOR: actor-msg
+pub type RequestId = u64;
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+pub enum AbdMsg<Value> {
+    Query(OpId),
+    AckQuery(OpId, Seq, Value),
+    Replicate(OpId, Seq, Value),
+    AckReplicate(OpId),
+}
+
+pub type OpId = (Id, RequestId); // requester and its request ID
+// ANCHOR_END: actor-msg
+use AbdMsg::*;
+
+// ANCHOR: actor-state
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AbdState<Value> {
+    seq: Seq,
+    val: Value,
+    phases: BTreeMap<OpId, AbdPhase<Value>>,
+    delivered: BTreeSet<(Id, RequestId)>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum AbdPhase<Value> {
+    Phase1 {
+        write: Option<Value>, // `None` for read
+        responses: BTreeMap<Id, (Seq, Value)>,
+    },
+    Phase2 {
+        read: Option<Value>, // `None` for write
+        acks: BTreeSet<Id>,
+    },
+}
+
+pub type Seq = (LogicalClock, Id); // `Id` for uniqueness
+pub type LogicalClock = u64;
+// ANCHOR_END: actor-state
+
+impl<Value> AbdState<Value> {
+    /// Indicates whether the server is coordinating any requests.
+    pub fn is_coordinating(&self) -> bool {
+        !self.phases.is_empty()
+    }
+}
+
+// ANCHOR: actor
+#[derive(Clone)]
+pub struct AbdActor<Value> {
+    pub peers: Vec<Id>,
+    pub write_back: WriteBack,
+    pub default_value: Value, // held by every server before the first write
+}
+
+/// Indicates whether a read replicates the value that it observed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WriteBack {
+    /// Always replicate, as described by the original algorithm.
+    Always,
+    /// Skip replication if the read quorum already agrees on a sequencer.
+    UnlessQuorumAgrees,
+    /// Like `UnlessQuorumAgrees`, but also only replicate to peers that are not known to
+    /// have the sequencer.
+    Targeted,
+}
+
+impl<Value> Actor for AbdActor<Value>
+where Value: Clone + Debug + Eq + Hash + Serialize + DeserializeOwned,
+{
+    type Msg = RegisterMsg<RequestId, Value, AbdMsg<Value>>;
+    type State = AbdState<Value>;
+    type Timer = ();
+
+    fn on_start(&self, _id: Id, _o: &mut Out<Self>) -> Self::State {
+        AbdState {
+            seq: (0, Id::from(0)),
+            val: self.default_value.clone(),
+            phases: Default::default(),
+            delivered: Default::default(),
+        }
+    }
+
+    fn on_msg(&self, id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        use RegisterMsg::*;
+        match msg {
+            Put(req_id, val) => {
+                if state.delivered.contains(&(src, req_id)) { return }
+
+                o.broadcast(&self.peers, &Internal(Query((src, req_id))));
+                let state = state.to_mut();
+                state.delivered.insert((src, req_id));
+                state.phases.insert((src, req_id), AbdPhase::Phase1 {
+                    write: Some(val),
+                    responses: {
+                        let mut responses = BTreeMap::default();
+                        responses.insert(id, (state.seq, state.val.clone()));
+                        responses
+                    },
+                });
+            }
+            Get(req_id) => {
+                if state.delivered.contains(&(src, req_id)) { return }
+
+                o.broadcast(&self.peers, &Internal(Query((src, req_id))));
+                let state = state.to_mut();
+                state.delivered.insert((src, req_id));
+                state.phases.insert((src, req_id), AbdPhase::Phase1 {
+                    write: None,
+                    responses: {
+                        let mut responses = BTreeMap::default();
+                        responses.insert(id, (state.seq, state.val.clone()));
+                        responses
+                    },
+                });
+            }
+            Internal(Query(op_id)) => {
+                o.send(
+                    src,
+                    Internal(AckQuery(op_id, state.seq, state.val.clone())));
+            }
+            Internal(AckQuery(op_id, seq, val))
+                if matches!(state.phases.get(&op_id),
+                            Some(AbdPhase::Phase1 { responses, .. })
+                            if !responses.contains_key(&src)) =>
+            {
+                let state = state.to_mut();
+                if let Some(AbdPhase::Phase1 { write, responses }) = state.phases.get_mut(&op_id) {
+                    responses.insert(src, (seq, val));
+                    if responses.len() == majority(self.peers.len() + 1) {
+                        // Quorum reached. Move to phase 2.
+                        let (requester, req_id) = op_id;
+
+                        // Determine sequencer and value.
+                        let (_, (seq, val)) = responses.iter()
+                            .max_by_key(|(_, (seq, _))| *seq)
+                            .unwrap();
+
+                        // A value held by a quorum is already replicated, so a
+                        // read can skip phase 2 if every response agrees.
+                        if write.is_none()
+                            && self.write_back != WriteBack::Always
+                            && responses.values().all(|(s, _)| s == seq)
+                        {
+                            o.send(requester, GetOk(req_id, val.clone()));
+                            state.phases.remove(&op_id);
+                            return;
+                        }
+                        let mut seq = *seq;
+                        let mut read = None;
+                        let val = if let Some(val) = write.take() {
+                            // Other writes coordinated by this server may have completed
+                            // phase 1 in the meantime, so the sequencer must also exceed
+                            // the local one to remain unique.
+                            seq = (seq.max(state.seq).0 + 1, id);
+                            val
+                        } else {
+                            read = Some(val.clone());
+                            val.clone()
+                        };
+
+                        // Self-send `AckReplicate`.
+                        let mut acks = BTreeSet::default();
+                        acks.insert(id);
+
+                        // Peers that reported the sequencer already have the value, so
+                        // they count as acks.
+                        if read.is_some() && self.write_back == WriteBack::Targeted {
+                            acks.extend(responses.iter()
+                                .filter(|(_, (s, _))| *s == seq)
+                                .map(|(peer_id, _)| *peer_id));
+                        }
+
+                        // Self-send `Replicate`.
+                        if seq > state.seq {
+                            state.seq = seq;
+                            state.val = val.clone();
+                        }
+
+                        // Those acks (or a single server) may already form a quorum, in
+                        // which case no `AckReplicate` is needed to complete the request.
+                        if acks.len() >= majority(self.peers.len() + 1) {
+                            let msg = if let Some(val) = read {
+                                GetOk(req_id, val)
+                            } else {
+                                PutOk(req_id)
+                            };
+                            o.send(requester, msg);
+                            state.phases.remove(&op_id);
+                            return;
+                        }
+
+                        o.broadcast(
+                            self.peers.iter().filter(|peer_id| !acks.contains(peer_id)),
+                            &Internal(Replicate(op_id, seq, val)));
+
+                        state.phases.insert(op_id, AbdPhase::Phase2 { read, acks });
+                    }
+                }
+            }
+            Internal(Replicate(op_id, seq, val)) => {
+                o.send(src, Internal(AckReplicate(op_id)));
+                if seq > state.seq {
+                    let state = state.to_mut();
+                    state.seq = seq;
+                    state.val = val;
+                }
+            }
+            Internal(AckReplicate(op_id))
+                if matches!(state.phases.get(&op_id),
+                            Some(AbdPhase::Phase2 { acks, .. })
+                            if !acks.contains(&src)) =>
+            {
+                let state = state.to_mut();
+                if let Some(AbdPhase::Phase2 { read, acks }) = state.phases.get_mut(&op_id) {
+                    acks.insert(src);
+                    if acks.len() >= majority(self.peers.len() + 1) {
+                        let (requester, req_id) = op_id;
+                        let msg = if let Some(val) = read.take() {
+                            GetOk(req_id, val)
+                        } else {
+                            PutOk(req_id)
+                        };
+                        o.send(requester, msg);
+                        state.phases.remove(&op_id);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+// ANCHOR_END: actor
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use retrying_client::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+
+    // ANCHOR: test
+    #[test]
+    fn is_linearizable_quick() {
+        let checker = base_model()
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 793);
+    }
+
+    #[test]
+    fn is_linearizable_quick_without_agreed_write_back() {
+        let checker = base_model()
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1]),
+                write_back: WriteBack::UnlessQuorumAgrees,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0]),
+                write_back: WriteBack::UnlessQuorumAgrees,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 555);
+
+        // Skipping the write-back must shrink the state space compared with `WriteBack::Always`.
+        let always = base_model()
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        assert!(checker.unique_state_count() < always.unique_state_count());
+    }
+
+    #[test]
+    fn is_linearizable_quick_with_targeted_write_back() {
+        let checker = base_model()
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1]),
+                write_back: WriteBack::Targeted,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0]),
+                write_back: WriteBack::Targeted,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 555);
+    }
+
+    #[test]
+    fn is_linearizable_despite_redelivery() {
+        let checker = base_model()
+            .init_network(Network::new_unordered_duplicating([]))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 127_485);
+    }
+
+    #[test]
+    fn is_linearizable_despite_concurrent_requests() {
+        // Both clients only send requests to server 0.
+        let checker = base_model()
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 1 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 1 })
+            .property(Expectation::Sometimes, "concurrent requests", |_, state| {
+                state.actor_states.iter().any(|s| {
+                    matches!(&**s, RegisterActorState::Server(s) if s.phases.len() > 1)
+                })
+            })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 641);
+    }
+
+    #[test]
+    fn value_chosen_despite_lossy_network() {
+        // The client sends its `Get` to server 1, so a reply from server 0 requires a retry.
+        let checker = ActorModel::new((), ())
+            .lossy_network(LossyNetwork::Yes)
+            .actor(RetryingRegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RetryingRegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RetryingRegisterActor::Client { put_count: 1, server_count: 2 })
+            .property(Expectation::Sometimes, "value chosen after retry", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    matches!(e.msg, RegisterMsg::GetOk(_, value) if *value != '?')
+                        && e.src == Id::from(0)
+                })
+            })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+    }
+
+    #[test]
+    fn supports_non_char_values() {
+        // `RegisterActor` clients only use `char`, so the requests are placed in the initial
+        // network instead, as if sent by clients 2 and 3.
+        let checker = ActorModel::new((), ())
+            .init_network(Network::new_unordered_nonduplicating([
+                Envelope { src: Id::from(2), dst: Id::from(0), msg: RegisterMsg::Put(1, 42) },
+                Envelope { src: Id::from(3), dst: Id::from(1), msg: RegisterMsg::Get(1) },
+            ]))
+            .actor(AbdActor {
+                peers: Id::vec_from(vec![1]),
+                write_back: WriteBack::Always,
+                default_value: 0_u64,
+            })
+            .actor(AbdActor {
+                peers: Id::vec_from(vec![0]),
+                write_back: WriteBack::Always,
+                default_value: 0_u64,
+            })
+            .property(Expectation::Always, "reads old or new value", |_, state| {
+                state.network.iter_deliverable().all(|e| {
+                    !matches!(e.msg, RegisterMsg::GetOk(_, value) if *value != 0 && *value != 42)
+                })
+            })
+            .property(Expectation::Sometimes, "reads old value", |_, state| {
+                state.network.iter_deliverable()
+                    .any(|e| matches!(e.msg, RegisterMsg::GetOk(_, 0)))
+            })
+            .property(Expectation::Sometimes, "reads new value", |_, state| {
+                state.network.iter_deliverable()
+                    .any(|e| matches!(e.msg, RegisterMsg::GetOk(_, 42)))
+            })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "enabled for --release only")]
+    fn is_linearizable() {
+        let checker = base_model()
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1, 2]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0, 2]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0, 1]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 51_060_036);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "enabled for --release only")]
+    fn is_linearizable_without_agreed_write_back() {
+        let checker = base_model()
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1, 2]),
+                write_back: WriteBack::UnlessQuorumAgrees,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0, 2]),
+                write_back: WriteBack::UnlessQuorumAgrees,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0, 1]),
+                write_back: WriteBack::UnlessQuorumAgrees,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 34_381_206);
+
+        // Skipping the write-back must shrink the state space compared with `WriteBack::Always`.
+        let always = base_model()
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1, 2]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0, 2]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0, 1]),
+                write_back: WriteBack::Always,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        assert!(checker.unique_state_count() < always.unique_state_count());
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "enabled for --release only")]
+    fn is_linearizable_with_targeted_write_back() {
+        let checker = base_model()
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![1, 2]),
+                write_back: WriteBack::Targeted,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0, 2]),
+                write_back: WriteBack::Targeted,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Server(AbdActor {
+                peers: Id::vec_from(vec![0, 1]),
+                write_back: WriteBack::Targeted,
+                default_value: '?',
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 15_991_974);
+    }
+
+    fn base_model()
+        -> ActorModel<
+            RegisterActor<AbdActor<char>>,
+            (),
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        ActorModel::new(
+                (),
+                LinearizabilityTester::new(Register('?'))
+            )
+            .init_network(Network::new_unordered_nonduplicating([]))
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.serialized_history().is_some()
+            })
+            .property(Expectation::Sometimes, "value chosen", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    if let RegisterMsg::GetOk(_, value) = e.msg {
+                        return *value != '?';
+                    }
+                    false
+                })
+            })
+            .property(Expectation::Eventually, "every client gets a reply", |_, state| {
+                state.actor_states.iter().all(|s| {
+                    !matches!(**s, RegisterActorState::Client { awaiting: Some(_), .. })
+                })
+            })
+            .record_msg_in(RegisterMsg::record_returns)
+            .record_msg_out(RegisterMsg::record_invocations)
+    }
+    // ANCHOR_END: test
+}
+
+/* ANCHOR_END: all */
@@ -0,0 +1,239 @@
+//! Provides a linearizable register by arranging servers in a chain, as described in "Chain
+//! Replication for Supporting High Throughput and Availability" by Robbert van Renesse and Fred B.
+//! Schneider. Writes enter at the head of the chain and are passed along to the tail, which
+//! replies to the client. Reads are served by the tail, so they only observe writes that every
+//! server has applied.
+//!
+//! Servers apply updates in sequence order and buffer any that arrive early, so the links between
+//! servers need not be FIFO. Recovering from a failed server additionally requires a configuration
+//! service that splices the chain, which is not implemented here.
+
+/* ANCHOR: all */
+use serde::{Deserialize, Serialize};
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{SocketAddrV4, Ipv4Addr};
+
+// ANCHOR: actor-msg
+type RequestId = u64;
+type Value = char;
+type Seq = u64;
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+enum ChainMsg {
+    ForwardPut(Id, RequestId, Value), // includes the client's ID
+    ForwardGet(Id, RequestId),
+    Update { seq: Seq, value: Value, request: (Id, RequestId) },
+}
+// ANCHOR_END: actor-msg
+use ChainMsg::*;
+
+// ANCHOR: actor-state
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct ChainState {
+    seq: Seq, // last update applied
+    value: Value,
+    delivered: BTreeSet<(Id, RequestId)>, // only used by the head
+    pending: BTreeMap<Seq, (Value, (Id, RequestId))>, // updates that arrived early
+}
+// ANCHOR_END: actor-state
+
+// ANCHOR: actor
+#[derive(Clone)]
+struct ChainActor {
+    chain: Vec<Id>, // every server, including this one, starting with the head
+}
+
+impl ChainActor {
+    fn head(&self) -> Id {
+        self.chain[0]
+    }
+
+    fn tail(&self) -> Id {
+        self.chain[self.chain.len() - 1]
+    }
+
+    fn successor(&self, id: Id) -> Option<Id> {
+        let index = self.chain.iter().position(|s| *s == id)?;
+        self.chain.get(index + 1).copied()
+    }
+
+    /// Passes an update down the chain, or replies to the client if this server is the tail.
+    fn propagate(&self, id: Id, seq: Seq, value: Value, request: (Id, RequestId),
+                 o: &mut Out<Self>) {
+        match self.successor(id) {
+            Some(successor) => {
+                o.send(successor, RegisterMsg::Internal(Update { seq, value, request }));
+            }
+            None => {
+                let (requester, req_id) = request;
+                o.send(requester, RegisterMsg::PutOk(req_id));
+            }
+        }
+    }
+}
+
+impl Actor for ChainActor {
+    type Msg = RegisterMsg<RequestId, Value, ChainMsg>;
+    type State = ChainState;
+    type Timer = ();
+
+    fn on_start(&self, _id: Id, _o: &mut Out<Self>) -> Self::State {
+        ChainState {
+            seq: 0,
+            value: '?',
+            delivered: Default::default(),
+            pending: Default::default(),
+        }
+    }
+
+    fn on_msg(&self, id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        use RegisterMsg::*;
+        let msg = match msg {
+            Put(req_id, value) => Internal(ForwardPut(src, req_id, value)),
+            Get(req_id) => Internal(ForwardGet(src, req_id)),
+            msg => msg,
+        };
+        match msg {
+            Internal(ForwardPut(requester, req_id, value)) => {
+                if id != self.head() {
+                    o.send(self.head(), Internal(ForwardPut(requester, req_id, value)));
+                    return;
+                }
+                if state.delivered.contains(&(requester, req_id)) { return }
+
+                // The head orders writes without waiting for earlier ones to reach the tail.
+                let state = state.to_mut();
+                state.seq += 1;
+                state.value = value;
+                state.delivered.insert((requester, req_id));
+                self.propagate(id, state.seq, value, (requester, req_id), o);
+            }
+            Internal(ForwardGet(requester, req_id)) => {
+                if id != self.tail() {
+                    o.send(self.tail(), Internal(ForwardGet(requester, req_id)));
+                    return;
+                }
+                o.send(requester, GetOk(req_id, state.value));
+            }
+            Internal(Update { seq, value, request }) if seq > state.seq => {
+                if state.pending.contains_key(&seq) { return }
+
+                // Skipping an update would lose its reply, so later ones wait for it.
+                let state = state.to_mut();
+                state.pending.insert(seq, (value, request));
+                while let Some((value, request)) = state.pending.remove(&(state.seq + 1)) {
+                    state.seq += 1;
+                    state.value = value;
+                    self.propagate(id, state.seq, value, request, o);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+// ANCHOR_END: actor
+
+// Running the program spawns actors on UDP ports 3000-3002, with the first serving as the head.
+// Messages are JSON-serialized.
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    let id0 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000));
+    let id1 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3001));
+    let id2 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3002));
+    let chain = vec![id0, id1, id2];
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (id0, ChainActor { chain: chain.clone() }),
+            (id1, ChainActor { chain: chain.clone() }),
+            (id2, ChainActor { chain }),
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+
+    // ANCHOR: test
+    #[test]
+    fn is_linearizable() {
+        // Each write visits every server in the chain, whereas each read only visits the tail.
+        for (length, state_count) in [(1, 4_243), (2, 5_527), (3, 7_145), (4, 7_683)] {
+            let checker = chain_model(length)
+                .checker().threads(num_cpus::get()).spawn_dfs().join();
+            checker.assert_properties();
+            assert_eq!(checker.unique_state_count(), state_count);
+        }
+    }
+
+    #[test]
+    fn is_linearizable_despite_reordering() {
+        // An update can overtake an earlier one, which must then be applied first.
+        let checker = chain_model(3)
+            .init_network(Network::new_unordered_nonduplicating([]))
+            .property(Expectation::Sometimes, "update buffered", |_, state| {
+                state.actor_states.iter().any(|s| {
+                    matches!(&**s, RegisterActorState::Server(s) if !s.pending.is_empty())
+                })
+            })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 7_217);
+    }
+
+    /// A chain of the specified length serving three clients.
+    fn chain_model(length: usize)
+        -> ActorModel<
+            RegisterActor<ChainActor>,
+            (),
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        let chain = Id::vec_from(0..length);
+        base_model()
+            .actors((0..length).map(|_| RegisterActor::Server(ChainActor {
+                chain: chain.clone(),
+            })))
+            .actor(RegisterActor::Client { put_count: 1, server_count: length })
+            .actor(RegisterActor::Client { put_count: 1, server_count: length })
+            .actor(RegisterActor::Client { put_count: 1, server_count: length })
+    }
+
+    fn base_model()
+        -> ActorModel<
+            RegisterActor<ChainActor>,
+            (),
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        ActorModel::new(
+                (),
+                LinearizabilityTester::new(Register('?'))
+            )
+            .init_network(Network::new_ordered([]))
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.serialized_history().is_some()
+            })
+            .property(Expectation::Sometimes, "value chosen", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    if let RegisterMsg::GetOk(_, value) = e.msg {
+                        return *value != '?';
+                    }
+                    false
+                })
+            })
+            .property(Expectation::Eventually, "every client gets a reply", |_, state| {
+                state.actor_states.iter().all(|s| {
+                    !matches!(**s, RegisterActorState::Client { awaiting: Some(_), .. })
+                })
+            })
+            .record_msg_in(RegisterMsg::record_returns)
+            .record_msg_out(RegisterMsg::record_invocations)
+    }
+    // ANCHOR_END: test
+}
+/* ANCHOR_END: all */
@@ -0,0 +1,166 @@
+//! Measures how long Stateright takes to check [`TwoPhaseSys`] for a range of resource manager
+//! counts, and writes the results to `plt-src/comparison-with-tlaplus.performance.dat`, which
+//! `build.sh` plots. TLC timings cannot be measured here, so they are carried over from the
+//! existing file, or left as `NaN` (which gnuplot skips) for new resource manager counts.
+//!
+//! ```sh
+//! cargo run --release --bin bench -- --rms 7..=10 --threads 4 --strategy dfs
+//! ```
+
+use comparison_with_tlaplus::TwoPhaseSys;
+use stateright::{Checker, Model};
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+const DEFAULT_OUTPUT: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"), "/../../plt-src/comparison-with-tlaplus.performance.dat");
+
+#[derive(Clone, Copy, Debug)]
+enum Strategy { Bfs, Dfs }
+
+#[derive(Debug)]
+struct Options {
+    rm_counts: RangeInclusive<usize>,
+    threads: usize,
+    strategy: Strategy,
+    output: PathBuf,
+}
+
+struct Row { states: usize, tlc: Option<String>, stateright: f64 }
+
+fn main() {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("USAGE:");
+            eprintln!("  bench [--rms FIRST..=LAST] [--threads N] [--strategy bfs|dfs] [--output PATH]");
+            std::process::exit(1);
+        }
+    };
+
+    let mut rows = read_rows(&options.output);
+    for rm_count in options.rm_counts.clone() {
+        let (states, secs) = check(rm_count, options.threads, options.strategy);
+        println!("RM={} states={} sec={:.3}", rm_count, states, secs);
+        let tlc = rows.remove(&rm_count).and_then(|row| row.tlc);
+        rows.insert(rm_count, Row { states, tlc, stateright: secs });
+    }
+    write_rows(&options.output, &rows);
+    println!("Wrote {}", options.output.display());
+}
+
+fn check(rm_count: usize, threads: usize, strategy: Strategy) -> (usize, f64) {
+    let builder = TwoPhaseSys { rms: 0..rm_count }.checker().threads(threads);
+    let start = Instant::now();
+    match strategy {
+        Strategy::Bfs => finish(builder.spawn_bfs().join(), start),
+        Strategy::Dfs => finish(builder.spawn_dfs().join(), start),
+    }
+}
+
+fn finish(checker: impl Checker<TwoPhaseSys>, start: Instant) -> (usize, f64) {
+    let secs = start.elapsed().as_secs_f64();
+    checker.assert_properties();
+    (checker.unique_state_count(), secs)
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        rm_counts: 7..=10,
+        threads: num_cpus::get(),
+        strategy: Strategy::Dfs,
+        output: PathBuf::from(DEFAULT_OUTPUT),
+    };
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| format!("Missing value for {}.", flag))?;
+        match flag.as_str() {
+            "--rms" => {
+                let (first, last) = value.split_once("..=")
+                    .ok_or_else(|| format!("Expected FIRST..=LAST, found {:?}.", value))?;
+                let first = first.parse().map_err(|_| format!("Invalid RM count {:?}.", first))?;
+                let last = last.parse().map_err(|_| format!("Invalid RM count {:?}.", last))?;
+                if first > last {
+                    return Err(format!("Expected FIRST <= LAST, found {:?}.", value));
+                }
+                options.rm_counts = first..=last;
+            }
+            "--threads" => {
+                options.threads = value.parse()
+                    .map_err(|_| format!("Invalid thread count {:?}.", value))?;
+            }
+            "--strategy" => {
+                options.strategy = match value.as_str() {
+                    "bfs" => Strategy::Bfs,
+                    "dfs" => Strategy::Dfs,
+                    _ => return Err(format!("Unknown strategy {:?}.", value)),
+                };
+            }
+            "--output" => options.output = PathBuf::from(value),
+            _ => return Err(format!("Unknown option {:?}.", flag)),
+        }
+    }
+    Ok(options)
+}
+
+/// Reads the rows of an existing data file, keyed by resource manager count. A missing file is
+/// treated as empty.
+fn read_rows(path: &Path) -> BTreeMap<usize, Row> {
+    parse_rows(&std::fs::read_to_string(path).unwrap_or_default())
+}
+
+fn parse_rows(content: &str) -> BTreeMap<usize, Row> {
+    content.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            match columns.as_slice() {
+                [rm_count, states, tlc, stateright] => Some((
+                    rm_count.parse().ok()?,
+                    Row {
+                        states: states.parse().ok()?,
+                        tlc: Some(tlc.to_string()).filter(|tlc| *tlc != "NaN"),
+                        stateright: stateright.parse().ok()?,
+                    })),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn write_rows(path: &Path, rows: &BTreeMap<usize, Row>) {
+    std::fs::write(path, format_rows(rows))
+        .unwrap_or_else(|err| panic!("Unable to write {}: {}", path.display(), err));
+}
+
+/// Formats rows like the committed data file, including its trailing blank line.
+fn format_rows(rows: &BTreeMap<usize, Row>) -> String {
+    let mut content = format!("{:<5}{:>14}{:>9}{:>15}\n", "#  RM", "States", "TLC", "Stateright");
+    for (rm_count, row) in rows {
+        content.push_str(&format!("{:>5}{:>14}{:>9}{:>15.3}\n",
+            rm_count, row.states, row.tlc.as_deref().unwrap_or("NaN"), row.stateright));
+    }
+    content.push('\n');
+    content
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rewrites_data_file_unchanged() {
+        let content = std::fs::read_to_string(DEFAULT_OUTPUT).unwrap();
+        assert_eq!(format_rows(&parse_rows(&content)), content);
+    }
+
+    #[test]
+    fn rejects_empty_rm_range() {
+        let args = |rms: &str| vec!["--rms".to_owned(), rms.to_owned()].into_iter();
+        assert_eq!(parse_options(args("3..=5")).unwrap().rm_counts, 3..=5);
+        assert_eq!(parse_options(args("5..=5")).unwrap().rm_counts, 5..=5);
+        assert!(parse_options(args("5..=3")).is_err());
+    }
+}
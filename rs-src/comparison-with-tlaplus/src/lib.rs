@@ -1,24 +1,29 @@
 //! This module implements a subset of the two phase commit specification presented in the paper
 //! ["Consensus on Transaction Commit"](https://www.microsoft.com/en-us/research/wp-content/uploads/2016/02/tr-2003-96.pdf)
-//! by Jim Gray and Leslie Lamport.
+//! by Jim Gray and Leslie Lamport. The paper's Paxos Commit specification is implemented by the
+//! [`paxos_commit`] module, and the [`tlc`] module renders paths in the format of a TLC error
+//! trace.
+
+pub mod paxos_commit;
+pub mod tlc;
 
 // ANCHOR: dependencies
-use stateright::{Model, Property};
+use stateright::{Model, Property, Representative, Rewrite, RewritePlan};
 use std::collections::BTreeSet;
 use std::hash::Hash;
 use std::ops::Range;
 // ANCHOR_END: dependencies
 
 // ANCHOR: constants
-type R = usize; // RM in 0..N
+pub type R = usize; // RM in 0..N
 
 #[derive(Clone)]
-struct TwoPhaseSys { pub rms: Range<R> }
+pub struct TwoPhaseSys { pub rms: Range<R> }
 // ANCHOR_END: constants
 
 // ANCHOR: variables
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
-struct TwoPhaseState {
+pub struct TwoPhaseState {
     rm_state: Vec<RmState>,
     tm_state: TmState,
     tm_prepared: Vec<bool>,
@@ -30,7 +35,7 @@ struct TwoPhaseState {
 #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 enum Message { Prepared { rm: R }, Commit, Abort }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 enum RmState { Working, Prepared, Committed, Aborted }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
@@ -38,8 +43,8 @@ enum TmState { Init, Committed, Aborted }
 // ANCHOR_END: types
 
 // ANCHOR: spec
-#[derive(Clone, Debug)]
-enum Action {
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
     TmRcvPrepared(R),
     TmCommit,
     TmAbort,
@@ -123,6 +128,10 @@ fn next_state(&self, last_state: &Self::State, action: Self::Action)
             state.rm_state[rm] = RmState::Aborted;
         }
     }
+    // Steps that leave the state unchanged are stuttering steps in TLA+. Without omitting them,
+    // no state would be terminal, and as the checker only reports `eventually` counterexamples
+    // at terminal states, that property would pass vacuously.
+    if state == *last_state { return None }
     Some(state)
 }
 // ANCHOR_END: next
@@ -135,11 +144,59 @@ fn properties(&self) -> Vec<Property<Self>> {
                 state.rm_state.iter().any(|s2|
                     s1 == &RmState::Aborted && s2 == &RmState::Committed))
         }),
+        Property::<Self>::always("type ok", |model, state| {
+            state.rm_state.len() == model.rms.len()
+                && state.tm_prepared.len() == model.rms.len()
+                && state.msgs.iter().all(|m| match m {
+                    Message::Prepared { rm } => model.rms.contains(rm),
+                    Message::Commit | Message::Abort => true,
+                })
+        }),
+        Property::<Self>::eventually("every RM reaches Committed or Aborted", |_, state| {
+            state.rm_state.iter().all(|s|
+                s == &RmState::Committed || s == &RmState::Aborted)
+        }),
+        Property::<Self>::sometimes("commit is sometimes reachable", |_, state| {
+            state.rm_state.iter().all(|s| s == &RmState::Committed)
+        }),
     ]
 }
 // ANCHOR_END: properties
 }
 
+// ANCHOR: symmetry
+/// Resource managers are interchangeable, so states that only differ by a permutation of RMs are
+/// equivalent. The representative sorts RMs by everything the state records about each of them.
+impl Representative for TwoPhaseState {
+    fn representative(&self) -> Self {
+        let per_rm: Vec<_> = self.rm_state.iter().enumerate()
+            .map(|(rm, s)| (s, self.tm_prepared[rm], self.msgs.contains(&Message::Prepared { rm })))
+            .collect();
+        let plan = RewritePlan::<R, _>::from_values_to_sort(&per_rm);
+        Self {
+            rm_state: plan.reindex(&self.rm_state),
+            tm_state: self.tm_state.clone(),
+            tm_prepared: plan.reindex(&self.tm_prepared),
+            msgs: self.msgs.rewrite(&plan),
+        }
+    }
+}
+
+impl Rewrite<R> for RmState {
+    fn rewrite<S>(&self, _plan: &RewritePlan<R, S>) -> Self { self.clone() }
+}
+
+impl Rewrite<R> for Message {
+    fn rewrite<S>(&self, plan: &RewritePlan<R, S>) -> Self {
+        match self {
+            Message::Prepared { rm } => Message::Prepared { rm: plan.rewrite(rm) },
+            Message::Commit => Message::Commit,
+            Message::Abort => Message::Abort,
+        }
+    }
+}
+// ANCHOR_END: symmetry
+
 // ANCHOR: configuration
 #[cfg(test)]
 #[test]
@@ -150,3 +207,18 @@ fn can_model_2pc() {
     	.assert_properties();
 }
 // ANCHOR_END: configuration
+
+#[cfg(test)]
+#[test]
+fn can_model_2pc_with_symmetry_reduction() {
+    use stateright::Checker;
+    let checker = TwoPhaseSys { rms: 0..5 }.checker()
+        .threads(num_cpus::get()).spawn_dfs().join();
+    checker.assert_properties();
+    assert_eq!(checker.unique_state_count(), 8_832);
+
+    let checker = TwoPhaseSys { rms: 0..5 }.checker()
+        .threads(num_cpus::get()).symmetry().spawn_dfs().join();
+    checker.assert_properties();
+    assert_eq!(checker.unique_state_count(), 314);
+}
@@ -0,0 +1,278 @@
+//! This module implements the Paxos Commit specification presented in the paper
+//! ["Consensus on Transaction Commit"](https://www.microsoft.com/en-us/research/wp-content/uploads/2016/02/tr-2003-96.pdf)
+//! by Jim Gray and Leslie Lamport. Unlike two-phase commit, which blocks if the transaction
+//! manager fails, each resource manager's vote is decided by its own instance of Paxos, so the
+//! protocol makes progress as long as a majority of acceptors are available.
+
+// ANCHOR: dependencies
+use crate::R;
+use stateright::{Model, Property};
+use std::collections::BTreeSet;
+use std::hash::Hash;
+use std::ops::Range;
+// ANCHOR_END: dependencies
+
+// ANCHOR: constants
+pub type A = usize; // Acceptor in 0..N
+pub type Ballot = usize;
+
+#[derive(Clone)]
+pub struct PaxosCommitSys {
+    pub rms: Range<R>,
+    pub acceptors: Range<A>,
+    pub ballots: Range<Ballot>, // must start at 0, which is reserved for the RM's own vote
+}
+// ANCHOR_END: constants
+
+// ANCHOR: variables
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PaxosCommitState {
+    rm_state: Vec<RmState>,
+    a_state: Vec<Vec<AcceptorState>>, // indexed by instance (RM) and then by acceptor
+    msgs: BTreeSet<Message>,
+}
+// ANCHOR_END: variables
+
+// ANCHOR: types
+/// `None` corresponds to the spec's ballot -1 and value "none", respectively.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct AcceptorState { mbal: Ballot, bal: Option<Ballot>, val: Option<Value> }
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Value { Prepared, Aborted }
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Message {
+    Phase1a { ins: R, bal: Ballot },
+    Phase1b { ins: R, mbal: Ballot, bal: Option<Ballot>, val: Option<Value>, acc: A },
+    Phase2a { ins: R, bal: Ballot, val: Value },
+    Phase2b { acc: A, ins: R, bal: Ballot, val: Value },
+    Commit,
+    Abort,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RmState { Working, Prepared, Committed, Aborted }
+// ANCHOR_END: types
+
+// ANCHOR: spec
+#[derive(Clone, Debug)]
+pub enum Action {
+    RmPrepare(R),
+    RmChooseToAbort(R),
+    RmRcvCommitMsg(R),
+    RmRcvAbortMsg(R),
+    Phase1a(Ballot, R),
+    Phase2a(Ballot, R, Value),
+    DecideCommit,
+    DecideAbort,
+    Phase1b(A, R, Ballot),
+    Phase2b(A, R, Ballot, Value),
+}
+
+impl Model for PaxosCommitSys {
+    type State = PaxosCommitState;
+    type Action = Action;
+// ANCHOR_END: spec
+
+// ANCHOR: init
+fn init_states(&self) -> Vec<Self::State> {
+    vec![PaxosCommitState {
+        rm_state: self.rms.clone().map(|_| RmState::Working).collect(),
+        a_state: self.rms.clone().map(|_| {
+            self.acceptors.clone()
+                .map(|_| AcceptorState { mbal: 0, bal: None, val: None })
+                .collect()
+        }).collect(),
+        msgs: Default::default(),
+    }]
+}
+// ANCHOR_END: init
+
+// ANCHOR: next
+fn actions(&self, state: &Self::State, actions: &mut Vec<Self::Action>) {
+    for rm in self.rms.clone() {
+        if state.rm_state[rm] == RmState::Working {
+            actions.push(Action::RmPrepare(rm));
+            actions.push(Action::RmChooseToAbort(rm));
+        }
+        if state.msgs.contains(&Message::Commit) {
+            actions.push(Action::RmRcvCommitMsg(rm));
+        }
+        if state.msgs.contains(&Message::Abort) {
+            actions.push(Action::RmRcvAbortMsg(rm));
+        }
+    }
+    for bal in self.ballots.clone().skip(1) {
+        for rm in self.rms.clone() {
+            actions.push(Action::Phase1a(bal, rm));
+            let already_sent = state.msgs.iter().any(|m| matches!(m,
+                Message::Phase2a { ins, bal: b, .. } if *ins == rm && *b == bal));
+            if already_sent { continue }
+            let mut vals = BTreeSet::new();
+            for majority in self.majorities() {
+                if let Some(val) = self.phase2a_value(state, bal, rm, &majority) {
+                    vals.insert(val);
+                }
+            }
+            for val in vals {
+                actions.push(Action::Phase2a(bal, rm, val));
+            }
+        }
+    }
+    if self.rms.clone().all(|rm| self.is_decided(state, rm, Value::Prepared)) {
+        actions.push(Action::DecideCommit);
+    }
+    if self.rms.clone().any(|rm| self.is_decided(state, rm, Value::Aborted)) {
+        actions.push(Action::DecideAbort);
+    }
+    for acc in self.acceptors.clone() {
+        for m in &state.msgs {
+            match *m {
+                Message::Phase1a { ins, bal } if state.a_state[ins][acc].mbal < bal => {
+                    actions.push(Action::Phase1b(acc, ins, bal));
+                }
+                Message::Phase2a { ins, bal, val } if state.a_state[ins][acc].mbal <= bal => {
+                    actions.push(Action::Phase2b(acc, ins, bal, val));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn next_state(&self, last_state: &Self::State, action: Self::Action)
+        -> Option<Self::State> {
+    let mut state = last_state.clone();
+    match action {
+        Action::RmPrepare(rm) => {
+            state.rm_state[rm] = RmState::Prepared;
+            state.msgs.insert(Message::Phase2a { ins: rm, bal: 0, val: Value::Prepared });
+        }
+        Action::RmChooseToAbort(rm) => {
+            state.rm_state[rm] = RmState::Aborted;
+            state.msgs.insert(Message::Phase2a { ins: rm, bal: 0, val: Value::Aborted });
+        }
+        Action::RmRcvCommitMsg(rm) => {
+            state.rm_state[rm] = RmState::Committed;
+        }
+        Action::RmRcvAbortMsg(rm) => {
+            state.rm_state[rm] = RmState::Aborted;
+        }
+        Action::Phase1a(bal, rm) => {
+            state.msgs.insert(Message::Phase1a { ins: rm, bal });
+        }
+        Action::Phase2a(bal, rm, val) => {
+            state.msgs.insert(Message::Phase2a { ins: rm, bal, val });
+        }
+        Action::DecideCommit => {
+            state.msgs.insert(Message::Commit);
+        }
+        Action::DecideAbort => {
+            state.msgs.insert(Message::Abort);
+        }
+        Action::Phase1b(acc, ins, bal) => {
+            let a = &mut state.a_state[ins][acc];
+            state.msgs.insert(Message::Phase1b {
+                ins, mbal: bal, bal: a.bal, val: a.val, acc,
+            });
+            a.mbal = bal;
+        }
+        Action::Phase2b(acc, ins, bal, val) => {
+            state.a_state[ins][acc] = AcceptorState { mbal: bal, bal: Some(bal), val: Some(val) };
+            state.msgs.insert(Message::Phase2b { acc, ins, bal, val });
+        }
+    }
+    Some(state)
+}
+// ANCHOR_END: next
+
+// ANCHOR: properties
+fn properties(&self) -> Vec<Property<Self>> {
+    vec![
+        Property::<Self>::always("consistent", |_, state| {
+           !state.rm_state.iter().any(|s1|
+                state.rm_state.iter().any(|s2|
+                    s1 == &RmState::Aborted && s2 == &RmState::Committed))
+        }),
+    ]
+}
+// ANCHOR_END: properties
+}
+
+// ANCHOR: helpers
+impl PaxosCommitSys {
+    /// Every set containing more than half of the acceptors, which corresponds to the spec's
+    /// `Majority` constant.
+    fn majorities(&self) -> Vec<BTreeSet<A>> {
+        let acceptors: Vec<A> = self.acceptors.clone().collect();
+        (0..1_usize << acceptors.len())
+            .map(|bits| {
+                acceptors.iter().enumerate()
+                    .filter(|(i, _)| bits & (1 << i) != 0)
+                    .map(|(_, acc)| *acc)
+                    .collect::<BTreeSet<A>>()
+            })
+            .filter(|set| 2 * set.len() > acceptors.len())
+            .collect()
+    }
+
+    /// The value that a leader of ballot `bal` must propose for `rm` after hearing from
+    /// `majority`, or `None` if some acceptor in `majority` has not responded.
+    fn phase2a_value(&self, state: &PaxosCommitState, bal: Ballot, rm: R,
+                     majority: &BTreeSet<A>) -> Option<Value> {
+        let mset: Vec<(Option<Ballot>, Option<Value>, A)> = state.msgs.iter()
+            .filter_map(|m| match *m {
+                Message::Phase1b { ins, mbal, bal: b, val, acc }
+                    if ins == rm && mbal == bal && majority.contains(&acc) => Some((b, val, acc)),
+                _ => None,
+            })
+            .collect();
+        if !majority.iter().all(|ac| mset.iter().any(|(_, _, acc)| acc == ac)) {
+            return None;
+        }
+        let maxbal = mset.iter().map(|(b, _, _)| *b).max().flatten();
+        match maxbal {
+            None => Some(Value::Aborted),
+            Some(maxbal) => mset.iter()
+                .find(|(b, _, _)| *b == Some(maxbal))
+                .and_then(|(_, val, _)| *val),
+        }
+    }
+
+    /// Whether a majority of acceptors accepted `val` for `rm` in the same ballot.
+    fn is_decided(&self, state: &PaxosCommitState, rm: R, val: Value) -> bool {
+        self.ballots.clone().any(|b| {
+            let count = self.acceptors.clone()
+                .filter(|acc| state.msgs.contains(&Message::Phase2b {
+                    acc: *acc, ins: rm, bal: b, val,
+                }))
+                .count();
+            2 * count > self.acceptors.len()
+        })
+    }
+}
+// ANCHOR_END: helpers
+
+// ANCHOR: configuration
+#[cfg(test)]
+#[test]
+fn can_model_paxos_commit_quick() {
+    use stateright::Checker;
+    let checker = PaxosCommitSys { rms: 0..1, acceptors: 0..3, ballots: 0..2 }.checker()
+        .threads(num_cpus::get()).spawn_dfs().join();
+    checker.assert_properties();
+    assert_eq!(checker.unique_state_count(), 1_461);
+}
+
+#[cfg(test)]
+#[test]
+#[cfg_attr(debug_assertions, ignore = "enabled for --release only")]
+fn can_model_paxos_commit() {
+    use stateright::Checker;
+    let checker = PaxosCommitSys { rms: 0..2, acceptors: 0..3, ballots: 0..2 }.checker()
+        .threads(num_cpus::get()).spawn_dfs().join();
+    checker.assert_properties();
+    assert_eq!(checker.unique_state_count(), 1_321_761);
+}
+// ANCHOR_END: configuration
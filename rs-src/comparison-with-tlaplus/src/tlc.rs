@@ -0,0 +1,426 @@
+//! Translates between [`TwoPhaseSys`](crate::TwoPhaseSys) and TLC's output for `TwoPhase.tla`.
+//!
+//! [`trace`] renders a path in the format of a TLC error trace, using the spec's variable names
+//! and values, so that a Stateright counterexample can be read alongside one produced by TLC. TLC
+//! also reports the source location of each action, which has no Rust equivalent and is omitted.
+//!
+//! [`parse_dump`] goes the other way, reading the states that TLC writes when run with `-dump`, so
+//! that they can be compared with the states that Stateright reaches. No dumps are committed, but
+//! one can be generated from `other-src/comparison-with-tlaplus` with
+//! `tlc -config dumps/rm2.cfg -dump dumps/rm2 TwoPhase.tla`, and likewise for `rm3`. Until then,
+//! the parser is only tested with hand-written dumps in TLC's format and with rendered traces.
+
+use crate::{Action, Message, RmState, TmState, TwoPhaseState};
+use stateright::Path;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Renders a path as a sequence of TLC states, each labeled with the action that produced it.
+pub fn trace(path: Path<TwoPhaseState, Action>) -> String {
+    let mut output = String::new();
+    let mut label = "Initial predicate".to_string();
+    for (i, (state, action)) in path.into_vec().into_iter().enumerate() {
+        if i > 0 { output.push('\n'); }
+        output.push_str(&format!("State {}: <{}>\n", i + 1, label));
+        output.push_str(&format!("/\\ rmState = {}\n", rm_state(&state.rm_state)));
+        output.push_str(&format!("/\\ tmState = {}\n", tm_state(&state.tm_state)));
+        output.push_str(&format!("/\\ tmPrepared = {}\n", tm_prepared(&state.tm_prepared)));
+        output.push_str(&format!("/\\ msgs = {}\n", msgs(&state.msgs)));
+        if let Some(action) = action {
+            label = action_label(&action);
+        }
+    }
+    output
+}
+
+fn action_label(action: &Action) -> String {
+    match action {
+        Action::TmRcvPrepared(rm) => format!("TMRcvPrepared({})", rm),
+        Action::TmCommit => "TMCommit".to_string(),
+        Action::TmAbort => "TMAbort".to_string(),
+        Action::RmPrepare(rm) => format!("RMPrepare({})", rm),
+        Action::RmChooseToAbort(rm) => format!("RMChooseToAbort({})", rm),
+        Action::RmRcvCommitMsg(rm) => format!("RMRcvCommitMsg({})", rm),
+        Action::RmRcvAbortMsg(rm) => format!("RMRcvAbortMsg({})", rm),
+    }
+}
+
+/// TLC prints a function over `RM` as `(0 :> v0 @@ 1 :> v1 @@ ...)`.
+fn rm_state(rm_state: &[RmState]) -> String {
+    let mappings: Vec<String> = rm_state.iter().enumerate()
+        .map(|(rm, s)| {
+            let s = match s {
+                RmState::Working => "working",
+                RmState::Prepared => "prepared",
+                RmState::Committed => "committed",
+                RmState::Aborted => "aborted",
+            };
+            format!("{} :> \"{}\"", rm, s)
+        })
+        .collect();
+    format!("({})", mappings.join(" @@ "))
+}
+
+fn tm_state(tm_state: &TmState) -> String {
+    match tm_state {
+        TmState::Init => "\"init\"",
+        TmState::Committed => "\"committed\"",
+        TmState::Aborted => "\"aborted\"",
+    }.to_string()
+}
+
+/// The Rust model tracks a flag per RM, while the TLA+ spec tracks the set of prepared RMs.
+fn tm_prepared(tm_prepared: &[bool]) -> String {
+    let rms: Vec<String> = tm_prepared.iter().enumerate()
+        .filter(|(_, prepared)| **prepared)
+        .map(|(rm, _)| rm.to_string())
+        .collect();
+    format!("{{{}}}", rms.join(", "))
+}
+
+fn msgs(msgs: &BTreeSet<Message>) -> String {
+    let msgs: Vec<String> = msgs.iter()
+        .map(|m| match m {
+            Message::Prepared { rm } => format!("[type |-> \"Prepared\", rm |-> {}]", rm),
+            Message::Commit => "[type |-> \"Commit\"]".to_string(),
+            Message::Abort => "[type |-> \"Abort\"]".to_string(),
+        })
+        .collect();
+    format!("{{{}}}", msgs.join(", "))
+}
+
+/// Parses the states of a TLC `-dump` file for `TwoPhase.tla`, in the order they appear.
+pub fn parse_dump(dump: &str) -> Result<Vec<TwoPhaseState>, String> {
+    let mut states = Vec::new();
+    let mut variables: Vec<(String, String)> = Vec::new();
+    for line in dump.lines().chain(std::iter::once("")) {
+        let line = line.trim();
+        if line.starts_with("State ") {
+            continue;
+        }
+        if line.is_empty() {
+            if !variables.is_empty() {
+                let variables = std::mem::take(&mut variables).into_iter()
+                    .map(|(name, value)| Ok((name, parse_value(&value)?)))
+                    .collect::<Result<_, String>>()?;
+                states.push(to_state(variables)?);
+            }
+            continue;
+        }
+        match line.strip_prefix("/\\ ").and_then(|line| line.split_once(" = ")) {
+            Some((name, value)) => variables.push((name.to_string(), value.to_string())),
+            // TLC wraps long values onto indented lines.
+            None => match variables.last_mut() {
+                Some((_, value)) => {
+                    value.push(' ');
+                    value.push_str(line);
+                }
+                None => return Err(format!("Expected a variable, found {:?}.", line)),
+            },
+        }
+    }
+    Ok(states)
+}
+
+/// The subset of TLA+ values that TLC prints for `TwoPhase.tla`.
+#[derive(Debug, PartialEq)]
+enum Value {
+    Int(usize),
+    Str(String),
+    Set(Vec<Value>),
+    Record(BTreeMap<String, Value>),
+    Function(Vec<(Value, Value)>),
+}
+
+fn to_state(mut variables: BTreeMap<String, Value>) -> Result<TwoPhaseState, String> {
+    let mut take = |name: &str| variables.remove(name)
+        .ok_or_else(|| format!("Missing variable {}.", name));
+    let rm_state = match take("rmState")? {
+        Value::Function(mappings) => {
+            let mut rm_state = Vec::new();
+            for (rm, (k, v)) in mappings.into_iter().enumerate() {
+                if k != Value::Int(rm) {
+                    return Err(format!("Expected RM {}, found {:?}.", rm, k));
+                }
+                rm_state.push(match v {
+                    Value::Str(s) if s == "working" => RmState::Working,
+                    Value::Str(s) if s == "prepared" => RmState::Prepared,
+                    Value::Str(s) if s == "committed" => RmState::Committed,
+                    Value::Str(s) if s == "aborted" => RmState::Aborted,
+                    v => return Err(format!("Invalid rmState value {:?}.", v)),
+                });
+            }
+            rm_state
+        }
+        v => return Err(format!("Invalid rmState {:?}.", v)),
+    };
+    let tm_state = match take("tmState")? {
+        Value::Str(s) if s == "init" => TmState::Init,
+        Value::Str(s) if s == "committed" => TmState::Committed,
+        Value::Str(s) if s == "aborted" => TmState::Aborted,
+        v => return Err(format!("Invalid tmState {:?}.", v)),
+    };
+    let mut tm_prepared = vec![false; rm_state.len()];
+    match take("tmPrepared")? {
+        Value::Set(rms) => for rm in rms {
+            match rm {
+                Value::Int(rm) if rm < tm_prepared.len() => tm_prepared[rm] = true,
+                v => return Err(format!("Invalid tmPrepared element {:?}.", v)),
+            }
+        },
+        v => return Err(format!("Invalid tmPrepared {:?}.", v)),
+    }
+    let msgs = match take("msgs")? {
+        Value::Set(msgs) => msgs.into_iter().map(to_message).collect::<Result<_, _>>()?,
+        v => return Err(format!("Invalid msgs {:?}.", v)),
+    };
+    Ok(TwoPhaseState { rm_state, tm_state, tm_prepared, msgs })
+}
+
+fn to_message(value: Value) -> Result<Message, String> {
+    if let Value::Record(fields) = &value {
+        match (fields.get("type"), fields.get("rm"), fields.len()) {
+            (Some(Value::Str(t)), Some(Value::Int(rm)), 2) if t == "Prepared" => {
+                return Ok(Message::Prepared { rm: *rm });
+            }
+            (Some(Value::Str(t)), None, 1) if t == "Commit" => return Ok(Message::Commit),
+            (Some(Value::Str(t)), None, 1) if t == "Abort" => return Ok(Message::Abort),
+            _ => {}
+        }
+    }
+    Err(format!("Invalid message {:?}.", value))
+}
+
+fn parse_value(input: &str) -> Result<Value, String> {
+    let mut parser = Parser { input, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != input.len() {
+        return Err(format!("Unexpected {:?}.", &input[parser.pos..]));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> { input: &'a str, pos: usize }
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        let rest = &self.input[self.pos..];
+        if let Some(string) = rest.strip_prefix('"') {
+            let len = string.find('"').ok_or("Unterminated string.")?;
+            self.pos += len + 2;
+            Ok(Value::Str(string[..len].to_string()))
+        } else if rest.starts_with(|c: char| c.is_ascii_digit()) {
+            let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            self.pos += len;
+            rest[..len].parse().map(Value::Int).map_err(|err| err.to_string())
+        } else if self.eat("{") {
+            Ok(Value::Set(self.list("}", |p| p.value())?))
+        } else if self.eat("[") {
+            let fields = self.list("]", |p| {
+                let name = p.identifier()?;
+                p.expect("|->")?;
+                Ok((name, p.value()?))
+            })?;
+            Ok(Value::Record(fields.into_iter().collect()))
+        } else if self.eat("(") {
+            Ok(Value::Function(self.list_with(")", "@@", |p| {
+                let k = p.value()?;
+                p.expect(":>")?;
+                Ok((k, p.value()?))
+            })?))
+        } else {
+            Err(format!("Unexpected {:?}.", rest))
+        }
+    }
+
+    fn list<T>(&mut self, close: &str, item: impl FnMut(&mut Self) -> Result<T, String>)
+            -> Result<Vec<T>, String> {
+        self.list_with(close, ",", item)
+    }
+
+    fn list_with<T>(&mut self, close: &str, separator: &str,
+                    mut item: impl FnMut(&mut Self) -> Result<T, String>)
+            -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        if self.eat(close) { return Ok(items) }
+        loop {
+            items.push(item(self)?);
+            if self.eat(close) { return Ok(items) }
+            self.expect(separator)?;
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        let rest = &self.input[self.pos..];
+        let len = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+        if len == 0 { return Err(format!("Expected an identifier, found {:?}.", rest)) }
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.input[self.pos..].starts_with(token);
+        if found { self.pos += token.len() }
+        found
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) { Ok(()) }
+        else { Err(format!("Expected {:?}, found {:?}.", token, &self.input[self.pos..])) }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn can_render_tlc_trace() {
+    use crate::TwoPhaseSys;
+    use stateright::Model;
+    let model = TwoPhaseSys { rms: 0..2 };
+    let path = Path::from_actions(&model, model.init_states()[0].clone(), &[
+        Action::RmPrepare(1),
+        Action::TmRcvPrepared(1),
+        Action::RmChooseToAbort(0),
+        Action::TmAbort,
+        Action::RmRcvAbortMsg(1),
+    ]).unwrap();
+    assert_eq!(trace(path), "\
+State 1: <Initial predicate>
+/\\ rmState = (0 :> \"working\" @@ 1 :> \"working\")
+/\\ tmState = \"init\"
+/\\ tmPrepared = {}
+/\\ msgs = {}
+
+State 2: <RMPrepare(1)>
+/\\ rmState = (0 :> \"working\" @@ 1 :> \"prepared\")
+/\\ tmState = \"init\"
+/\\ tmPrepared = {}
+/\\ msgs = {[type |-> \"Prepared\", rm |-> 1]}
+
+State 3: <TMRcvPrepared(1)>
+/\\ rmState = (0 :> \"working\" @@ 1 :> \"prepared\")
+/\\ tmState = \"init\"
+/\\ tmPrepared = {1}
+/\\ msgs = {[type |-> \"Prepared\", rm |-> 1]}
+
+State 4: <RMChooseToAbort(0)>
+/\\ rmState = (0 :> \"aborted\" @@ 1 :> \"prepared\")
+/\\ tmState = \"init\"
+/\\ tmPrepared = {1}
+/\\ msgs = {[type |-> \"Prepared\", rm |-> 1]}
+
+State 5: <TMAbort>
+/\\ rmState = (0 :> \"aborted\" @@ 1 :> \"prepared\")
+/\\ tmState = \"aborted\"
+/\\ tmPrepared = {1}
+/\\ msgs = {[type |-> \"Prepared\", rm |-> 1], [type |-> \"Abort\"]}
+
+State 6: <RMRcvAbortMsg(1)>
+/\\ rmState = (0 :> \"aborted\" @@ 1 :> \"aborted\")
+/\\ tmState = \"aborted\"
+/\\ tmPrepared = {1}
+/\\ msgs = {[type |-> \"Prepared\", rm |-> 1], [type |-> \"Abort\"]}
+");
+}
+
+#[cfg(test)]
+#[test]
+fn can_parse_tlc_dump() {
+    // In the format of `tlc -dump` output for two RMs, including a value wrapped across lines.
+    let dump = "\
+State 1:
+/\\ rmState = (0 :> \"working\" @@ 1 :> \"working\")
+/\\ tmState = \"init\"
+/\\ tmPrepared = {}
+/\\ msgs = {}
+
+State 2:
+/\\ rmState = (0 :> \"prepared\" @@ 1 :> \"aborted\")
+/\\ tmState = \"init\"
+/\\ tmPrepared = {0}
+/\\ msgs = {[type |-> \"Prepared\", rm |-> 0]}
+
+State 3:
+/\\ rmState = (0 :> \"committed\" @@ 1 :> \"prepared\")
+/\\ tmState = \"committed\"
+/\\ tmPrepared = {0, 1}
+/\\ msgs = { [rm |-> 0, type |-> \"Prepared\"], [type |-> \"Prepared\", rm |-> 1],
+             [type |-> \"Commit\"] }
+";
+    assert_eq!(parse_dump(dump), Ok(vec![
+        TwoPhaseState {
+            rm_state: vec![RmState::Working, RmState::Working],
+            tm_state: TmState::Init,
+            tm_prepared: vec![false, false],
+            msgs: BTreeSet::new(),
+        },
+        TwoPhaseState {
+            rm_state: vec![RmState::Prepared, RmState::Aborted],
+            tm_state: TmState::Init,
+            tm_prepared: vec![true, false],
+            msgs: vec![Message::Prepared { rm: 0 }].into_iter().collect(),
+        },
+        TwoPhaseState {
+            rm_state: vec![RmState::Committed, RmState::Prepared],
+            tm_state: TmState::Committed,
+            tm_prepared: vec![true, true],
+            msgs: vec![
+                Message::Prepared { rm: 0 },
+                Message::Prepared { rm: 1 },
+                Message::Commit,
+            ].into_iter().collect(),
+        },
+    ]));
+    assert_eq!(parse_dump(""), Ok(Vec::new()));
+}
+
+#[cfg(test)]
+#[test]
+fn can_parse_rendered_trace() {
+    use crate::TwoPhaseSys;
+    use stateright::Model;
+    let model = TwoPhaseSys { rms: 0..3 };
+    let path = Path::from_actions(&model, model.init_states()[0].clone(), &[
+        Action::RmPrepare(2),
+        Action::TmRcvPrepared(2),
+        Action::RmChooseToAbort(0),
+        Action::TmAbort,
+        Action::RmRcvAbortMsg(2),
+    ]).unwrap();
+    assert_eq!(parse_dump(&trace(path.clone())), Ok(path.into_states()));
+}
+
+#[cfg(test)]
+#[test]
+fn rejects_malformed_tlc_dump() {
+    let state = |rm_state: &str, tm_state: &str, tm_prepared: &str, msgs: &str| format!(
+        "/\\ rmState = {}\n/\\ tmState = {}\n/\\ tmPrepared = {}\n/\\ msgs = {}\n",
+        rm_state, tm_state, tm_prepared, msgs);
+    let working = "(0 :> \"working\")";
+    for (dump, error) in vec![
+        ("rmState = {}".to_string(), "Expected a variable, found \"rmState = {}\"."),
+        ("/\\ tmState = \"init\"".to_string(), "Missing variable rmState."),
+        (state(working, "\"init\"", "{}", "{} {}"), "Unexpected \"{}\"."),
+        (state(working, "\"init", "{}", "{}"), "Unterminated string."),
+        (state(working, "init", "{}", "{}"), "Unexpected \"init\"."),
+        (state(working, "\"init\"", "{0 1}", "{}"), "Expected \",\", found \"1}\"."),
+        (state("(1 :> \"working\")", "\"init\"", "{}", "{}"), "Expected RM 0, found Int(1)."),
+        (state("(0 :> \"idle\")", "\"init\"", "{}", "{}"),
+         "Invalid rmState value Str(\"idle\")."),
+        (state(working, "\"done\"", "{}", "{}"), "Invalid tmState Str(\"done\")."),
+        (state(working, "\"init\"", "{1}", "{}"), "Invalid tmPrepared element Int(1)."),
+        (state(working, "\"init\"", "{}", "{[type |-> \"Commit\", rm |-> 0]}"),
+         "Invalid message Record({\"rm\": Int(0), \"type\": Str(\"Commit\")})."),
+        (state(working, "\"init\"", "{}", "{[ |-> 0]}"),
+         "Expected an identifier, found \"|-> 0]}\"."),
+    ] {
+        assert_eq!(parse_dump(&dump), Err(error.to_string()), "{}", dump);
+    }
+}
@@ -0,0 +1,336 @@
+//! Provides eventually consistent replicated data types via state-based CRDTs (Conflict-free
+//! Replicated Data Types), as described in "[A comprehensive study of Convergent and Commutative
+//! Replicated Data Types](https://hal.inria.fr/inria-00555588/document)" by Marc Shapiro, Nuno
+//! Preguiça, Carlos Baquero, and Marek Zawirski.
+//!
+//! Each replica applies updates locally without coordinating with its peers and then gossips its
+//! entire state. Receiving replicas merge the state into their own, and the merge is commutative,
+//! associative, and idempotent, so replicas that have seen the same updates converge regardless of
+//! the order in which messages are delivered or how often they are duplicated.
+
+/* ANCHOR: all */
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use stateright::actor::*;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::net::{SocketAddrV4, Ipv4Addr};
+use std::ops::Range;
+use std::time::Duration;
+
+// ANCHOR: crdt
+/// A state-based CRDT.
+trait Crdt: Clone + Debug + Default + Eq + Hash {
+    type Op: Clone + Debug;
+    type Value: Debug + Eq;
+
+    /// Applies a local update at the specified replica.
+    fn apply(&mut self, replica: Id, op: &Self::Op);
+
+    /// Incorporates another replica's state, yielding the least state that includes both.
+    fn merge(&mut self, other: &Self);
+
+    #[cfg_attr(not(test), allow(dead_code))] // only read by the tests
+    fn value(&self) -> Self::Value;
+}
+
+/// A register whose concurrent writes are resolved by keeping the one with the largest timestamp.
+/// Timestamps are Lamport clocks, with ties broken by replica ID, so every write is ordered.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[derive(Serialize, Deserialize)]
+struct LwwRegister {
+    timestamp: (u64, Id),
+    value: Option<char>,
+}
+
+impl Crdt for LwwRegister {
+    type Op = char;
+    type Value = Option<char>;
+
+    fn apply(&mut self, replica: Id, value: &char) {
+        self.timestamp = (self.timestamp.0 + 1, replica);
+        self.value = Some(*value);
+    }
+
+    fn merge(&mut self, other: &Self) {
+        if other.timestamp > self.timestamp {
+            *self = other.clone();
+        }
+    }
+
+    fn value(&self) -> Option<char> {
+        self.value
+    }
+}
+
+/// A counter that can only be incremented. Each replica counts its own increments, and the value
+/// is the sum of the replicas' counts.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[derive(Serialize, Deserialize)]
+struct GCounter {
+    counts: BTreeMap<Id, u64>,
+}
+
+impl Crdt for GCounter {
+    type Op = (); // increment
+    type Value = u64;
+
+    fn apply(&mut self, replica: Id, _op: &()) {
+        *self.counts.entry(replica).or_default() += 1;
+    }
+
+    fn merge(&mut self, other: &Self) {
+        for (replica, count) in &other.counts {
+            let mine = self.counts.entry(*replica).or_default();
+            *mine = (*mine).max(*count);
+        }
+    }
+
+    fn value(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+/// A set that tags every addition uniquely and only removes the tags that the removing replica has
+/// observed. An addition therefore wins over a concurrent removal of the same element.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[derive(Serialize, Deserialize)]
+struct OrSet {
+    adds: BTreeSet<(char, Tag)>,
+    removes: BTreeSet<(char, Tag)>, // tombstones, which are never discarded
+}
+
+type Tag = (Id, u64); // the adding replica and the number of additions it had made
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Serialize, Deserialize)]
+enum OrSetOp {
+    Add(char),
+    Remove(char),
+}
+
+impl Crdt for OrSet {
+    type Op = OrSetOp;
+    type Value = BTreeSet<char>;
+
+    fn apply(&mut self, replica: Id, op: &OrSetOp) {
+        match op {
+            OrSetOp::Add(element) => {
+                let count = self.adds.iter().filter(|(_, (r, _))| *r == replica).count();
+                self.adds.insert((*element, (replica, count as u64 + 1)));
+            }
+            OrSetOp::Remove(element) => {
+                let observed: Vec<_> = self.adds.iter()
+                    .filter(|(e, _)| e == element)
+                    .copied()
+                    .collect();
+                self.removes.extend(observed);
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.adds.extend(other.adds.iter().copied());
+        self.removes.extend(other.removes.iter().copied());
+    }
+
+    fn value(&self) -> BTreeSet<char> {
+        self.adds.difference(&self.removes).map(|(element, _)| *element).collect()
+    }
+}
+// ANCHOR_END: crdt
+
+// ANCHOR: actor-msg
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+enum CrdtMsg<C> {
+    Gossip(C),
+}
+// ANCHOR_END: actor-msg
+use CrdtMsg::*;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum CrdtTimer {
+    /// Prompts a replica to apply its next update.
+    Update,
+}
+
+const UPDATE_INTERVAL: Range<Duration> = Duration::from_secs(1)..Duration::from_secs(2);
+
+// ANCHOR: actor-state
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct CrdtState<C> {
+    crdt: C,
+    applied: usize, // number of updates applied from `ops`
+}
+// ANCHOR_END: actor-state
+
+// ANCHOR: actor
+#[derive(Clone)]
+struct CrdtActor<C: Crdt> {
+    peers: Vec<Id>,
+    ops: Vec<C::Op>, // local updates, applied one per timeout
+}
+
+impl<C: Crdt> Actor for CrdtActor<C> {
+    type Msg = CrdtMsg<C>;
+    type State = CrdtState<C>;
+    type Timer = CrdtTimer;
+
+    fn on_start(&self, _id: Id, o: &mut Out<Self>) -> Self::State {
+        if !self.ops.is_empty() {
+            o.set_timer(CrdtTimer::Update, UPDATE_INTERVAL);
+        }
+        CrdtState {
+            crdt: C::default(),
+            applied: 0,
+        }
+    }
+
+    fn on_msg(&self, _id: Id, state: &mut Cow<Self::State>,
+              _src: Id, msg: Self::Msg, _o: &mut Out<Self>) {
+        let Gossip(crdt) = msg;
+        let mut merged = state.crdt.clone();
+        merged.merge(&crdt);
+        if merged != state.crdt {
+            state.to_mut().crdt = merged;
+        }
+    }
+
+    fn on_timeout(&self, id: Id, state: &mut Cow<Self::State>,
+                  _timer: &Self::Timer, o: &mut Out<Self>) {
+        let Some(op) = self.ops.get(state.applied) else { return };
+
+        let state = state.to_mut();
+        state.crdt.apply(id, op);
+        state.applied += 1;
+
+        // Broadcasting every update suffices on a lossless network. Deployments over lossy links
+        // would additionally gossip periodically.
+        o.broadcast(&self.peers, &Gossip(state.crdt.clone()));
+        if state.applied < self.ops.len() {
+            o.set_timer(CrdtTimer::Update, UPDATE_INTERVAL);
+        }
+    }
+}
+// ANCHOR_END: actor
+
+// Running the program spawns replicas on UDP ports 3000-3002. Messages are JSON-serialized, and
+// each replica's state is logged after it acts when `RUST_LOG=debug` is set.
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    match std::env::args().nth(1).as_deref() {
+        Some("lww-register") => spawn_replicas::<LwwRegister>([vec!['A'], vec!['B'], vec!['C']]),
+        Some("g-counter") => spawn_replicas::<GCounter>([vec![(), ()], vec![()], vec![(); 3]]),
+        _ => spawn_replicas::<OrSet>([
+            vec![OrSetOp::Add('A'), OrSetOp::Remove('B')],
+            vec![OrSetOp::Add('B')],
+            vec![OrSetOp::Remove('A'), OrSetOp::Add('C')],
+        ]),
+    }
+}
+
+fn spawn_replicas<C>(ops: [Vec<C::Op>; 3])
+where C: Crdt + Serialize + DeserializeOwned + Send + 'static,
+      C::Op: Send + Sync,
+{
+    let ids = [3000, 3001, 3002].map(|port| Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)));
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        ids.iter().zip(ops).map(|(id, ops)| (*id, CrdtActor::<C> {
+            peers: ids.iter().copied().filter(|peer| peer != id).collect(),
+            ops,
+        })).collect()).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::*;
+
+    // ANCHOR: test
+    #[test]
+    fn lww_register_converges() {
+        let checker = crdt_model::<LwwRegister>(vec![vec!['A'], vec!['B', 'C'], vec!['D']])
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 786);
+    }
+
+    #[test]
+    fn g_counter_converges() {
+        let checker = crdt_model::<GCounter>(vec![vec![(), ()], vec![()], vec![()]])
+            // No increment is lost, even though replicas increment concurrently.
+            .property(Expectation::Eventually, "counts every increment", |_, state| {
+                state.actor_states.iter().all(|s| s.crdt.value() == 4)
+            })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 5_722);
+    }
+
+    #[test]
+    fn or_set_converges() {
+        let checker = crdt_model::<OrSet>(vec![
+                vec![OrSetOp::Add('A')],
+                vec![OrSetOp::Remove('A'), OrSetOp::Add('B')],
+                vec![OrSetOp::Add('A')],
+            ])
+            // A removal only affects the additions that the removing replica had observed.
+            .property(Expectation::Sometimes, "removal observed", |model, state| {
+                is_quiescent(model, state)
+                    && !state.actor_states[0].crdt.value().contains(&'A')
+            })
+            .property(Expectation::Sometimes, "concurrent addition wins", |model, state| {
+                is_quiescent(model, state)
+                    && state.actor_states[0].crdt.value().contains(&'A')
+                    && state.actor_states.iter().any(|s| !s.crdt.removes.is_empty())
+            })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 2_064);
+    }
+
+    /// Every replica has applied its updates, and no message would change its recipient's state.
+    fn is_quiescent<C: Crdt>(model: &ActorModel<CrdtActor<C>>,
+                             state: &ActorModelState<CrdtActor<C>>) -> bool {
+        let applied_all = model.actors.iter().zip(&state.actor_states)
+            .all(|(actor, s)| s.applied == actor.ops.len());
+        let delivered_all = state.network.iter_deliverable().all(|e| {
+            let Gossip(crdt) = e.msg;
+            let mut merged = state.actor_states[usize::from(e.dst)].crdt.clone();
+            merged.merge(crdt);
+            merged == state.actor_states[usize::from(e.dst)].crdt
+        });
+        applied_all && delivered_all
+    }
+
+    fn has_converged<C: Crdt>(state: &ActorModelState<CrdtActor<C>>) -> bool {
+        let first = &state.actor_states[0].crdt;
+        state.actor_states.iter().all(|s| s.crdt == *first && s.crdt.value() == first.value())
+    }
+
+    /// Each replica applies the specified updates and gossips with every other replica.
+    fn crdt_model<C: Crdt>(ops: Vec<Vec<C::Op>>) -> ActorModel<CrdtActor<C>> {
+        let replica_count = ops.len();
+        ActorModel::new((), ())
+            .actors(ops.into_iter().enumerate().map(|(i, ops)| CrdtActor {
+                peers: Id::vec_from(0..replica_count).into_iter()
+                    .filter(|id| usize::from(*id) != i)
+                    .collect(),
+                ops,
+            }))
+            .property(Expectation::Always, "converged when quiescent", |model, state| {
+                !is_quiescent(model, state) || has_converged(state)
+            })
+            .property(Expectation::Eventually, "converged", |model, state| {
+                is_quiescent(model, state) && has_converged(state)
+            })
+            .property(Expectation::Sometimes, "diverged", |_, state| !has_converged(state))
+    }
+    // ANCHOR_END: test
+}
+/* ANCHOR_END: all */
@@ -1,3 +1,5 @@
+// `main` follows the tests, in the order that the book presents them.
+#![allow(clippy::items_after_test_module)]
 /* ANCHOR: all */
 use stateright::actor::{*, register::*};
 use std::borrow::Cow; // COW == clone-on-write
@@ -0,0 +1,325 @@
+//! Provides a linearizable register by having a primary server order every request and
+//! synchronously replicate the result to backup servers before replying. If a backup suspects that
+//! the primary has failed, it starts a new "view" that excludes the primary, and the next server in
+//! line takes over.
+//!
+//! Servers take turns as primary in a fixed order, so view `v` is led by the server at index `v`,
+//! and the servers after it are its backups. A server that has been replaced is never readmitted,
+//! which keeps the example simple at the cost of shrinking the group with each failover.
+
+/* ANCHOR: all */
+use serde::{Deserialize, Serialize};
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::net::{SocketAddrV4, Ipv4Addr};
+use std::ops::Range;
+use std::time::Duration;
+
+// ANCHOR: actor-msg
+type RequestId = u64;
+type Value = char;
+type View = usize; // index of the view's primary in the list of servers
+type OpNum = u64;
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+enum PrimaryBackupMsg {
+    ForwardPut(Id, RequestId, Value), // includes the client's ID
+    ForwardGet(Id, RequestId),
+    Replicate { view: View, op: OpNum, value: Value, request: (Id, RequestId) },
+    ReplicateOk { view: View, op: OpNum },
+    Heartbeat { view: View },
+    StartView { view: View },
+}
+// ANCHOR_END: actor-msg
+use PrimaryBackupMsg::*;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum PrimaryBackupTimer {
+    /// Reminds the primary to reassure backups that it is available.
+    Heartbeat,
+    /// Indicates that a backup has not heard from the primary in a while.
+    Failover,
+}
+
+const HEARTBEAT_INTERVAL: Range<Duration> = Duration::from_millis(500)..Duration::from_millis(500);
+const FAILOVER_TIMEOUT: Range<Duration> = Duration::from_secs(2)..Duration::from_secs(3);
+
+// ANCHOR: actor-state
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct PrimaryBackupState {
+    view: View,
+    op: OpNum, // last operation applied in `view`
+    value: Value,
+    delivered: BTreeSet<(Id, RequestId)>,
+    in_flight: Option<InFlight>, // only used by the primary
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct InFlight {
+    op: OpNum,
+    requester: Id,
+    reply: RegisterMsg<RequestId, Value, PrimaryBackupMsg>,
+    acks: BTreeSet<Id>,
+}
+// ANCHOR_END: actor-state
+
+// ANCHOR: actor
+#[derive(Clone)]
+struct PrimaryBackupActor {
+    servers: Vec<Id>, // every server, including this one, in the order they serve as primary
+}
+
+impl PrimaryBackupActor {
+    fn primary(&self, view: View) -> Id {
+        self.servers[view]
+    }
+
+    fn backups(&self, view: View) -> &[Id] {
+        &self.servers[view + 1..]
+    }
+
+    /// Adopts a later view, abandoning any request that was being coordinated in the earlier one.
+    fn enter_view(&self, id: Id, state: &mut PrimaryBackupState, view: View, o: &mut Out<Self>) {
+        state.view = view;
+        state.op = 0;
+        state.in_flight = None;
+        self.set_timers(id, view, o);
+    }
+
+    fn set_timers(&self, id: Id, view: View, o: &mut Out<Self>) {
+        if id == self.primary(view) {
+            o.cancel_timer(PrimaryBackupTimer::Failover);
+            o.set_timer(PrimaryBackupTimer::Heartbeat, HEARTBEAT_INTERVAL);
+        } else if self.backups(view).contains(&id) {
+            o.set_timer(PrimaryBackupTimer::Failover, FAILOVER_TIMEOUT);
+        } else {
+            o.cancel_timer(PrimaryBackupTimer::Heartbeat);
+            o.cancel_timer(PrimaryBackupTimer::Failover);
+        }
+    }
+
+    fn on_request(&self, id: Id, state: &mut Cow<PrimaryBackupState>,
+                  requester: Id, req_id: RequestId, put: Option<Value>, o: &mut Out<Self>) {
+        let primary = self.primary(state.view);
+        if id != primary {
+            let forward = match put {
+                Some(value) => ForwardPut(requester, req_id, value),
+                None => ForwardGet(requester, req_id),
+            };
+            o.send(primary, RegisterMsg::Internal(forward));
+            return;
+        }
+        if state.in_flight.is_some() || state.delivered.contains(&(requester, req_id)) { return }
+
+        let state = state.to_mut();
+        if let Some(value) = put {
+            state.value = value;
+        }
+        state.op += 1;
+        state.delivered.insert((requester, req_id));
+        let reply = match put {
+            Some(_) => RegisterMsg::PutOk(req_id),
+            None => RegisterMsg::GetOk(req_id, state.value),
+        };
+
+        // Reads are replicated too, which confirms that the view has not changed in the interim.
+        let backups = self.backups(state.view);
+        if backups.is_empty() {
+            o.send(requester, reply);
+            return;
+        }
+        o.broadcast(backups, &RegisterMsg::Internal(Replicate {
+            view: state.view,
+            op: state.op,
+            value: state.value,
+            request: (requester, req_id),
+        }));
+        state.in_flight = Some(InFlight {
+            op: state.op,
+            requester,
+            reply,
+            acks: Default::default(),
+        });
+    }
+}
+
+impl Actor for PrimaryBackupActor {
+    type Msg = RegisterMsg<RequestId, Value, PrimaryBackupMsg>;
+    type State = PrimaryBackupState;
+    type Timer = PrimaryBackupTimer;
+
+    fn on_start(&self, id: Id, o: &mut Out<Self>) -> Self::State {
+        self.set_timers(id, 0, o);
+        PrimaryBackupState {
+            view: 0,
+            op: 0,
+            value: '?',
+            delivered: Default::default(),
+            in_flight: None,
+        }
+    }
+
+    fn on_msg(&self, id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        use RegisterMsg::*;
+        match msg {
+            Put(req_id, value) => self.on_request(id, state, src, req_id, Some(value), o),
+            Get(req_id) => self.on_request(id, state, src, req_id, None, o),
+            Internal(ForwardPut(requester, req_id, value)) => {
+                self.on_request(id, state, requester, req_id, Some(value), o);
+            }
+            Internal(ForwardGet(requester, req_id)) => {
+                self.on_request(id, state, requester, req_id, None, o);
+            }
+            Internal(Replicate { view, op, value, request }) => {
+                if view < state.view || view == state.view && op <= state.op { return }
+
+                let state = state.to_mut();
+                if view > state.view {
+                    self.enter_view(id, state, view, o);
+                }
+                state.op = op;
+                state.value = value;
+                state.delivered.insert(request);
+                o.send(src, Internal(ReplicateOk { view, op }));
+            }
+            Internal(ReplicateOk { view, op })
+                if view == state.view
+                    && matches!(&state.in_flight,
+                                Some(in_flight) if in_flight.op == op
+                                    && !in_flight.acks.contains(&src)) =>
+            {
+                let state = state.to_mut();
+                let in_flight = state.in_flight.as_mut().unwrap();
+                in_flight.acks.insert(src);
+                if in_flight.acks.len() == self.backups(view).len() {
+                    let in_flight = state.in_flight.take().unwrap();
+                    o.send(in_flight.requester, in_flight.reply);
+                }
+            }
+            Internal(Heartbeat { view }) if view == state.view => {
+                o.set_timer(PrimaryBackupTimer::Failover, FAILOVER_TIMEOUT);
+            }
+            Internal(Heartbeat { view } | StartView { view }) if view > state.view => {
+                self.enter_view(id, state.to_mut(), view, o);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_timeout(&self, id: Id, state: &mut Cow<Self::State>,
+                  timer: &Self::Timer, o: &mut Out<Self>) {
+        match timer {
+            PrimaryBackupTimer::Heartbeat if id == self.primary(state.view) => {
+                let view = state.view;
+                o.broadcast(self.backups(view), &RegisterMsg::Internal(Heartbeat { view }));
+                o.set_timer(PrimaryBackupTimer::Heartbeat, HEARTBEAT_INTERVAL);
+            }
+            PrimaryBackupTimer::Failover if self.backups(state.view).contains(&id) => {
+                // Every backup is a member of the next view, and the first backup leads it.
+                let view = state.view + 1;
+                self.enter_view(id, state.to_mut(), view, o);
+                let peers: Vec<Id> = self.servers.iter().copied().filter(|s| *s != id).collect();
+                o.broadcast(&peers, &RegisterMsg::Internal(StartView { view }));
+            }
+            _ => {}
+        }
+    }
+}
+// ANCHOR_END: actor
+
+// Running the program spawns actors on UDP ports 3000-3002. Messages are JSON-serialized.
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    let id0 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000));
+    let id1 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3001));
+    let id2 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3002));
+    let servers = vec![id0, id1, id2];
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (id0, PrimaryBackupActor { servers: servers.clone() }),
+            (id1, PrimaryBackupActor { servers: servers.clone() }),
+            (id2, PrimaryBackupActor { servers }),
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+
+    // ANCHOR: test
+    #[test]
+    fn is_linearizable_quick() {
+        let checker = base_model()
+            .actor(RegisterActor::Server(PrimaryBackupActor {
+                servers: Id::vec_from(vec![0, 1]),
+            }))
+            .actor(RegisterActor::Server(PrimaryBackupActor {
+                servers: Id::vec_from(vec![0, 1]),
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 70_958);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "enabled for --release only")]
+    fn is_linearizable() {
+        // Three servers allow two failovers, so a single client keeps the state space manageable.
+        let checker = base_model()
+            .actor(RegisterActor::Server(PrimaryBackupActor {
+                servers: Id::vec_from(vec![0, 1, 2]),
+            }))
+            .actor(RegisterActor::Server(PrimaryBackupActor {
+                servers: Id::vec_from(vec![0, 1, 2]),
+            }))
+            .actor(RegisterActor::Server(PrimaryBackupActor {
+                servers: Id::vec_from(vec![0, 1, 2]),
+            }))
+            .actor(RegisterActor::Client { put_count: 2, server_count: 3 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 4_664_664);
+    }
+
+    fn base_model()
+        -> ActorModel<
+            RegisterActor<PrimaryBackupActor>,
+            (),
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        ActorModel::new(
+                (),
+                LinearizabilityTester::new(Register('?'))
+            )
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.serialized_history().is_some()
+            })
+            .property(Expectation::Sometimes, "value chosen", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    if let RegisterMsg::GetOk(_, value) = e.msg {
+                        return *value != '?';
+                    }
+                    false
+                })
+            })
+            // Only the primary replies, so a reply from another server implies a failover.
+            .property(Expectation::Sometimes, "value chosen after failover", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    matches!(e.msg, RegisterMsg::GetOk(_, value) if *value != '?')
+                        && e.src != Id::from(0)
+                })
+            })
+            .record_msg_in(RegisterMsg::record_returns)
+            .record_msg_out(RegisterMsg::record_invocations)
+    }
+    // ANCHOR_END: test
+}
+/* ANCHOR_END: all */
@@ -0,0 +1,493 @@
+//! Provides a linearizable register by having a cluster of actors agree upon a sequence of commands
+//! (a replicated log) via the Raft algorithm, which is described in "[In Search of an Understandable
+//! Consensus Algorithm](https://raft.github.io/raft.pdf)" by Diego Ongaro and John Ousterhout.
+//!
+//! Unlike the Multi-Paxos implementation in `achieving-consensus`, a server only proposes commands
+//! after winning an election, and it only needs to replicate its own log, as an election can only
+//! be won by a server whose log is at least as up to date as a majority of the cluster.
+
+/* ANCHOR: all */
+use serde::{Deserialize, Serialize};
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{SocketAddrV4, Ipv4Addr};
+use std::ops::Range;
+use std::time::Duration;
+
+// ANCHOR: actor-msg
+type RequestId = u64;
+type Value = char;
+type Term = u64;
+type LogIndex = usize; // starts at 1, so 0 precedes the first entry
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+enum RaftMsg {
+    Forward(Command), // a client request received by a follower
+    RequestVote { term: Term, last_log_index: LogIndex, last_log_term: Term },
+    Vote { term: Term, granted: bool },
+    AppendEntries {
+        term: Term,
+        prev_log_index: LogIndex,
+        prev_log_term: Term,
+        entries: Vec<Entry>,
+        leader_commit: LogIndex,
+    },
+    AppendEntriesReply { term: Term, success: bool, match_index: LogIndex },
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+enum Command {
+    Get(Id, RequestId),
+    Put(Id, RequestId, Value),
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    term: Term,
+    command: Command,
+}
+// ANCHOR_END: actor-msg
+use RaftMsg::*;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum RaftTimer {
+    /// Prompts a follower or candidate to start an election.
+    Election,
+    /// Prompts a leader to reassert its leadership.
+    Heartbeat,
+}
+
+// Election timeouts are randomized so that servers rarely start competing elections.
+const ELECTION_TIMEOUT: Range<Duration> = Duration::from_millis(1500)..Duration::from_millis(3000);
+const HEARTBEAT_INTERVAL: Range<Duration> = Duration::from_millis(500)..Duration::from_millis(500);
+
+// ANCHOR: actor-state
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct RaftState {
+    // persistent state
+    term: Term,
+    voted_for: Option<Id>,
+    log: Vec<Entry>,
+
+    // volatile state
+    commit_index: LogIndex,
+    value: Value, // reflects the commands through `commit_index`
+    role: Role,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Role {
+    Follower { leader: Option<Id> },
+    Candidate { votes: BTreeSet<Id> },
+    Leader { next_index: BTreeMap<Id, LogIndex>, match_index: BTreeMap<Id, LogIndex> },
+}
+
+impl RaftState {
+    fn term_at(&self, index: LogIndex) -> Option<Term> {
+        if index == 0 { return Some(0) }
+        self.log.get(index - 1).map(|entry| entry.term)
+    }
+
+    fn last_log_term(&self) -> Term {
+        self.log.last().map_or(0, |entry| entry.term)
+    }
+}
+// ANCHOR_END: actor-state
+
+// ANCHOR: actor
+#[derive(Clone)]
+struct RaftActor {
+    peers: Vec<Id>,
+}
+
+impl RaftActor {
+    /// Adopts a newer term as a follower.
+    fn step_down(&self, state: &mut RaftState, term: Term, o: &mut Out<Self>) {
+        state.term = term;
+        state.voted_for = None;
+        state.role = Role::Follower { leader: None };
+        o.cancel_timer(RaftTimer::Heartbeat);
+        o.set_timer(RaftTimer::Election, ELECTION_TIMEOUT);
+    }
+
+    fn become_leader(&self, state: &mut RaftState, o: &mut Out<Self>) {
+        state.role = Role::Leader {
+            next_index: self.peers.iter().map(|peer| (*peer, state.log.len() + 1)).collect(),
+            match_index: self.peers.iter().map(|peer| (*peer, 0)).collect(),
+        };
+        o.cancel_timer(RaftTimer::Election);
+        o.set_timer(RaftTimer::Heartbeat, HEARTBEAT_INTERVAL);
+        for &peer in &self.peers {
+            self.send_append_entries(state, peer, o);
+        }
+    }
+
+    /// Sends a peer the entries that it is missing, which doubles as a heartbeat.
+    fn send_append_entries(&self, state: &RaftState, peer: Id, o: &mut Out<Self>) {
+        if let Role::Leader { next_index, .. } = &state.role {
+            let prev_log_index = next_index[&peer] - 1;
+            o.send(peer, RegisterMsg::Internal(AppendEntries {
+                term: state.term,
+                prev_log_index,
+                prev_log_term: state.term_at(prev_log_index).unwrap(),
+                entries: state.log[prev_log_index..].to_vec(),
+                leader_commit: state.commit_index,
+            }));
+        }
+    }
+
+    /// Applies newly committed commands to the register, replying to clients if leading.
+    fn commit(&self, state: &mut RaftState, commit_index: LogIndex, o: &mut Out<Self>) {
+        let is_leader = matches!(state.role, Role::Leader { .. });
+        for entry in &state.log[state.commit_index..commit_index] {
+            match entry.command {
+                Command::Put(requester, req_id, value) => {
+                    state.value = value;
+                    if is_leader {
+                        o.send(requester, RegisterMsg::PutOk(req_id));
+                    }
+                }
+                Command::Get(requester, req_id) => {
+                    if is_leader {
+                        o.send(requester, RegisterMsg::GetOk(req_id, state.value));
+                    }
+                }
+            }
+        }
+        state.commit_index = commit_index;
+    }
+
+    /// Commits through the last entry from the current term that a majority has replicated.
+    /// Entries from earlier terms are only committed indirectly, as a majority replicating them
+    /// does not guarantee that a future leader will have them.
+    fn advance_commit_index(&self, state: &mut RaftState, o: &mut Out<Self>) {
+        let quorum = majority(self.peers.len() + 1);
+        let commit_index = match &state.role {
+            Role::Leader { match_index, .. } => {
+                (state.commit_index + 1..=state.log.len()).rev().find(|&index| {
+                    state.log[index - 1].term == state.term
+                        && 1 + match_index.values().filter(|m| **m >= index).count() >= quorum
+                })
+            }
+            _ => None,
+        };
+        if let Some(commit_index) = commit_index {
+            self.commit(state, commit_index, o);
+        }
+    }
+
+    fn on_command(&self, state: &mut Cow<RaftState>, command: Command, forwarded: bool,
+                  o: &mut Out<Self>) {
+        match &state.role {
+            Role::Leader { .. } => {
+                if state.log.iter().any(|entry| entry.command == command) { return }
+
+                let state = state.to_mut();
+                state.log.push(Entry { term: state.term, command });
+                for &peer in &self.peers {
+                    self.send_append_entries(state, peer, o);
+                }
+                self.advance_commit_index(state, o); // in case there are no peers
+            }
+            Role::Follower { leader: Some(leader) } if !forwarded => {
+                o.send(*leader, RegisterMsg::Internal(Forward(command)));
+            }
+            _ => {} // no known leader, so the request is dropped
+        }
+    }
+}
+
+impl Actor for RaftActor {
+    type Msg = RegisterMsg<RequestId, Value, RaftMsg>;
+    type State = RaftState;
+    type Timer = RaftTimer;
+
+    fn on_start(&self, _id: Id, o: &mut Out<Self>) -> Self::State {
+        o.set_timer(RaftTimer::Election, ELECTION_TIMEOUT);
+        RaftState {
+            term: 0,
+            voted_for: None,
+            log: Vec::new(),
+            commit_index: 0,
+            value: '?',
+            role: Role::Follower { leader: None },
+        }
+    }
+
+    fn on_msg(&self, _id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        use RegisterMsg::*;
+        if let Internal(RequestVote { term, .. } | Vote { term, .. }
+                        | AppendEntries { term, .. } | AppendEntriesReply { term, .. }) = &msg {
+            if *term > state.term {
+                self.step_down(state.to_mut(), *term, o);
+            }
+        }
+
+        match msg {
+            Put(req_id, value) => self.on_command(state, Command::Put(src, req_id, value), false, o),
+            Get(req_id) => self.on_command(state, Command::Get(src, req_id), false, o),
+            Internal(Forward(command)) => self.on_command(state, command, true, o),
+            Internal(RequestVote { term, last_log_index, last_log_term }) => {
+                // Only vote for a candidate whose log is at least as up to date.
+                let granted = term == state.term
+                    && state.voted_for.is_none_or(|candidate| candidate == src)
+                    && (last_log_term, last_log_index) >= (state.last_log_term(), state.log.len());
+                if granted && state.voted_for.is_none() {
+                    state.to_mut().voted_for = Some(src);
+                    o.set_timer(RaftTimer::Election, ELECTION_TIMEOUT);
+                }
+                o.send(src, Internal(Vote { term: state.term, granted }));
+            }
+            Internal(Vote { term, granted: true })
+                if term == state.term
+                    && matches!(&state.role,
+                                Role::Candidate { votes } if !votes.contains(&src)) =>
+            {
+                let state = state.to_mut();
+                if let Role::Candidate { votes } = &mut state.role {
+                    votes.insert(src);
+                    if votes.len() >= majority(self.peers.len() + 1) {
+                        self.become_leader(state, o);
+                    }
+                }
+            }
+            Internal(AppendEntries { term, prev_log_index, prev_log_term, entries, leader_commit }) => {
+                if term < state.term {
+                    o.send(src, Internal(AppendEntriesReply {
+                        term: state.term,
+                        success: false,
+                        match_index: 0,
+                    }));
+                    return;
+                }
+
+                // The sender leads the current term.
+                let state = state.to_mut();
+                state.role = Role::Follower { leader: Some(src) };
+                o.set_timer(RaftTimer::Election, ELECTION_TIMEOUT);
+                if state.term_at(prev_log_index) != Some(prev_log_term) {
+                    // Hint where the logs might match, so the leader can back up.
+                    let match_index = (prev_log_index - 1).min(state.log.len());
+                    o.send(src, Internal(AppendEntriesReply { term, success: false, match_index }));
+                    return;
+                }
+
+                // Only truncate conflicting entries, as the message may be stale.
+                for (offset, entry) in entries.iter().enumerate() {
+                    let index = prev_log_index + 1 + offset;
+                    match state.term_at(index) {
+                        Some(term) if term == entry.term => {}
+                        Some(_) => {
+                            state.log.truncate(index - 1);
+                            state.log.push(*entry);
+                        }
+                        None => state.log.push(*entry),
+                    }
+                }
+                let match_index = prev_log_index + entries.len();
+                let commit_index = leader_commit.min(match_index);
+                if commit_index > state.commit_index {
+                    self.commit(state, commit_index, o);
+                }
+                o.send(src, Internal(AppendEntriesReply { term, success: true, match_index }));
+            }
+            Internal(AppendEntriesReply { term, success, match_index: index })
+                if term == state.term && matches!(state.role, Role::Leader { .. }) =>
+            {
+                let state = state.to_mut();
+                if let Role::Leader { next_index, match_index } = &mut state.role {
+                    let (next, matched) = (next_index.get_mut(&src), match_index.get_mut(&src));
+                    if let (Some(next), Some(matched)) = (next, matched) {
+                        if success {
+                            *matched = (*matched).max(index);
+                            *next = (*next).max(index + 1);
+                        } else {
+                            *next = (*next).min(index + 1);
+                        }
+                    }
+                }
+                if success {
+                    self.advance_commit_index(state, o);
+                } else {
+                    self.send_append_entries(state, src, o);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_timeout(&self, id: Id, state: &mut Cow<Self::State>,
+                  timer: &Self::Timer, o: &mut Out<Self>) {
+        match timer {
+            RaftTimer::Election if !matches!(state.role, Role::Leader { .. }) => {
+                let state = state.to_mut();
+                state.term += 1;
+                state.voted_for = Some(id);
+                state.role = Role::Candidate { votes: std::iter::once(id).collect() };
+                o.set_timer(RaftTimer::Election, ELECTION_TIMEOUT);
+                o.broadcast(&self.peers, &RegisterMsg::Internal(RequestVote {
+                    term: state.term,
+                    last_log_index: state.log.len(),
+                    last_log_term: state.last_log_term(),
+                }));
+                if majority(self.peers.len() + 1) == 1 {
+                    self.become_leader(state, o);
+                }
+            }
+            RaftTimer::Heartbeat if matches!(state.role, Role::Leader { .. }) => {
+                for &peer in &self.peers {
+                    self.send_append_entries(state, peer, o);
+                }
+                o.set_timer(RaftTimer::Heartbeat, HEARTBEAT_INTERVAL);
+            }
+            _ => {}
+        }
+    }
+}
+// ANCHOR_END: actor
+
+// Running the program spawns actors on UDP ports 3000-3002. Messages are JSON-serialized.
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    let id0 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000));
+    let id1 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3001));
+    let id2 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3002));
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (id0, RaftActor { peers: vec![id1, id2] }),
+            (id1, RaftActor { peers: vec![id0, id2] }),
+            (id2, RaftActor { peers: vec![id0, id1] }),
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+
+    // ANCHOR: test
+    #[test]
+    fn satisfies_all_properties_quick() {
+        // Two terms allow a leader to be replaced.
+        let checker = base_model(2)
+            .actor(RegisterActor::Server(RaftActor {
+                peers: Id::vec_from(vec![1]),
+            }))
+            .actor(RegisterActor::Server(RaftActor {
+                peers: Id::vec_from(vec![0]),
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 93_875);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "enabled for --release only")]
+    fn is_linearizable_with_concurrent_clients() {
+        // A second client multiplies the state space, so this test is limited to a single term,
+        // in which the leader is never replaced.
+        let checker = base_model(1)
+            .actor(RegisterActor::Server(RaftActor {
+                peers: Id::vec_from(vec![1]),
+            }))
+            .actor(RegisterActor::Server(RaftActor {
+                peers: Id::vec_from(vec![0]),
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_no_discovery("election safety");
+        checker.assert_no_discovery("log matching");
+        checker.assert_no_discovery("linearizable");
+        checker.assert_any_discovery("value chosen");
+        assert_eq!(checker.unique_state_count(), 1_049_118);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "enabled for --release only")]
+    fn is_linearizable_with_three_servers() {
+        // A single term keeps the state space manageable, as with concurrent clients.
+        let checker = base_model(1)
+            .actor(RegisterActor::Server(RaftActor {
+                peers: Id::vec_from(vec![1, 2]),
+            }))
+            .actor(RegisterActor::Server(RaftActor {
+                peers: Id::vec_from(vec![0, 2]),
+            }))
+            .actor(RegisterActor::Server(RaftActor {
+                peers: Id::vec_from(vec![0, 1]),
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 3 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_no_discovery("election safety");
+        checker.assert_no_discovery("log matching");
+        checker.assert_no_discovery("linearizable");
+        checker.assert_any_discovery("value chosen");
+        assert_eq!(checker.unique_state_count(), 10_022_840);
+    }
+
+    fn servers(state: &ActorModelState<RegisterActor<RaftActor>,
+                                        LinearizabilityTester<Id, Register<char>>>)
+        -> impl Iterator<Item = &RaftState>
+    {
+        state.actor_states.iter().filter_map(|s| match &**s {
+            RegisterActorState::Server(s) => Some(s),
+            _ => None,
+        })
+    }
+
+    /// Elections can repeat indefinitely, so the model stops exploring after `max_term`.
+    fn base_model(max_term: Term)
+        -> ActorModel<
+            RegisterActor<RaftActor>,
+            Term,
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        ActorModel::new(
+                max_term,
+                LinearizabilityTester::new(Register('?'))
+            )
+            .within_boundary(|max_term, state| servers(state).all(|s| s.term <= *max_term))
+            .property(Expectation::Always, "election safety", |_, state| {
+                // At most one leader per term.
+                let mut terms = BTreeSet::new();
+                servers(state)
+                    .filter(|s| matches!(s.role, Role::Leader { .. }))
+                    .all(|s| terms.insert(s.term))
+            })
+            .property(Expectation::Always, "log matching", |_, state| {
+                // Logs with an entry of the same index and term are identical up to that entry.
+                servers(state).all(|a| servers(state).all(|b| {
+                    a.log.iter().zip(&b.log).enumerate().all(|(index, (x, y))| {
+                        x.term != y.term || a.log[..=index] == b.log[..=index]
+                    })
+                }))
+            })
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.serialized_history().is_some()
+            })
+            .property(Expectation::Sometimes, "value chosen", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    if let RegisterMsg::GetOk(_, value) = e.msg {
+                        return *value != '?';
+                    }
+                    false
+                })
+            })
+            .property(Expectation::Sometimes, "leader replaced", |_, state| {
+                servers(state).any(|s| matches!(s.role, Role::Leader { .. }) && s.term > 1)
+            })
+            .record_msg_in(RegisterMsg::record_returns)
+            .record_msg_out(RegisterMsg::record_invocations)
+    }
+    // ANCHOR_END: test
+}
+/* ANCHOR_END: all */
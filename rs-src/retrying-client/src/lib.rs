@@ -0,0 +1,293 @@
+//! Provides [`RetryingRegisterActor`], a drop-in replacement for Stateright's `RegisterActor` whose
+//! clients resend a request to the next server if no reply arrives before a timeout. The built-in
+//! client waits forever for a lost message, so only this client makes progress on a lossy network.
+//!
+//! Note that a retry reuses the request ID, so a server that deduplicates by `(Id, RequestId)`
+//! will not apply it twice, but a different server has no way to know that the request was
+//! already served. A `Put` whose first attempt was merely delayed can therefore take effect again
+//! after later writes, so a history that includes retries is not generally linearizable.
+
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+type RequestId = u64;
+
+/// Either a client that retries, or a server actor being validated. Clients and servers are
+/// configured exactly as with `RegisterActor`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RetryingRegisterActor<ServerActor> {
+    /// A client that [`RegisterMsg::Put`]s `put_count` values before sending a
+    /// [`RegisterMsg::Get`], resending each request to the next server upon a timeout. As with
+    /// `RegisterActor`, a client with a `put_count` of zero sends nothing.
+    Client {
+        put_count: usize,
+        server_count: usize,
+    },
+    /// A server actor being validated.
+    Server(ServerActor),
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RetryingRegisterActorState<ServerState> {
+    Client {
+        awaiting: Option<RequestId>,
+        op_count: u64,
+        server: Id, // where the request was most recently sent
+    },
+    Server(ServerState),
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum RetryingRegisterTimer<ServerTimer> {
+    /// Indicates that a client should resend its request.
+    Resend,
+    Server(ServerTimer),
+}
+
+impl<ServerActor> RetryingRegisterActor<ServerActor> {
+    /// Builds the client's request for an operation, following the same scheme for request IDs and
+    /// values as `RegisterActor`.
+    fn request<InternalMsg>(id: Id, op_count: u64, put_count: usize, server_count: usize)
+        -> RegisterMsg<RequestId, char, InternalMsg>
+    {
+        let index = usize::from(id) as u64;
+        let client_index = (index - server_count as u64) as u8;
+        let request_id = (op_count + 1) * index;
+        if op_count >= put_count as u64 {
+            RegisterMsg::Get(request_id)
+        } else if op_count == 0 {
+            RegisterMsg::Put(request_id, (b'A' + client_index) as char)
+        } else {
+            RegisterMsg::Put(request_id, (b'Z' - client_index) as char)
+        }
+    }
+}
+
+impl<ServerActor, InternalMsg> Actor for RetryingRegisterActor<ServerActor>
+where
+    ServerActor: Actor<Msg = RegisterMsg<RequestId, char, InternalMsg>>,
+    InternalMsg: Clone + Debug + Eq + Hash,
+{
+    type Msg = RegisterMsg<RequestId, char, InternalMsg>;
+    type State = RetryingRegisterActorState<ServerActor::State>;
+    type Timer = RetryingRegisterTimer<ServerActor::Timer>;
+
+    fn name(&self) -> String {
+        match self {
+            RetryingRegisterActor::Client { .. } => "Client".to_owned(),
+            RetryingRegisterActor::Server(s) => {
+                let n = s.name();
+                if n.is_empty() { "Server".to_owned() } else { n }
+            }
+        }
+    }
+
+    fn on_start(&self, id: Id, o: &mut Out<Self>) -> Self::State {
+        match self {
+            RetryingRegisterActor::Client { put_count, server_count } => {
+                let index = usize::from(id);
+                if index < *server_count {
+                    panic!("RetryingRegisterActor clients must be added to the model after servers.");
+                }
+
+                let server = Id::from(index % server_count);
+                if *put_count == 0 {
+                    // Like `RegisterActor`, a client without writes sends nothing.
+                    return RetryingRegisterActorState::Client {
+                        awaiting: None,
+                        op_count: 0,
+                        server,
+                    };
+                }
+                let request = Self::request(id, 0, *put_count, *server_count);
+                let awaiting = match request {
+                    RegisterMsg::Put(req_id, _) | RegisterMsg::Get(req_id) => Some(req_id),
+                    _ => None,
+                };
+                o.send(server, request);
+                o.set_timer(RetryingRegisterTimer::Resend, model_timeout());
+                RetryingRegisterActorState::Client { awaiting, op_count: 0, server }
+            }
+            RetryingRegisterActor::Server(server_actor) => {
+                let mut server_out = Out::new();
+                let state = server_actor.on_start(id, &mut server_out);
+                wrap_server_out(server_out, o);
+                RetryingRegisterActorState::Server(state)
+            }
+        }
+    }
+
+    fn on_msg(&self, id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        use RetryingRegisterActor as A;
+        use RetryingRegisterActorState as S;
+        match (self, &**state) {
+            (
+                A::Client { put_count, server_count },
+                S::Client { awaiting: Some(awaiting), op_count, server },
+            ) => {
+                match msg {
+                    RegisterMsg::PutOk(req_id) if req_id == *awaiting => {
+                        let op_count = op_count + 1;
+                        let server = Id::from(
+                            (usize::from(id) + op_count as usize) % server_count);
+                        let request = Self::request(id, op_count, *put_count, *server_count);
+                        let awaiting = match request {
+                            RegisterMsg::Put(req_id, _) | RegisterMsg::Get(req_id) => Some(req_id),
+                            _ => None,
+                        };
+                        o.send(server, request);
+                        o.set_timer(RetryingRegisterTimer::Resend, model_timeout());
+                        *state = Cow::Owned(S::Client { awaiting, op_count, server });
+                    }
+                    RegisterMsg::GetOk(req_id, _) if req_id == *awaiting => {
+                        o.cancel_timer(RetryingRegisterTimer::Resend);
+                        *state = Cow::Owned(S::Client {
+                            awaiting: None,
+                            op_count: op_count + 1,
+                            server: *server,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            (A::Server(server_actor), S::Server(server_state)) => {
+                let mut server_state = Cow::Borrowed(server_state);
+                let mut server_out = Out::new();
+                server_actor.on_msg(id, &mut server_state, src, msg, &mut server_out);
+                if let Cow::Owned(server_state) = server_state {
+                    *state = Cow::Owned(S::Server(server_state));
+                }
+                wrap_server_out(server_out, o);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_timeout(&self, id: Id, state: &mut Cow<Self::State>,
+                  timer: &Self::Timer, o: &mut Out<Self>) {
+        use RetryingRegisterActor as A;
+        use RetryingRegisterActorState as S;
+        match (self, &**state, timer) {
+            (
+                A::Client { put_count, server_count },
+                S::Client { awaiting: Some(awaiting), op_count, server },
+                RetryingRegisterTimer::Resend,
+            ) => {
+                // Moving on to the next server also handles one that is unreachable.
+                let server = Id::from((usize::from(*server) + 1) % server_count);
+                o.send(server, Self::request(id, *op_count, *put_count, *server_count));
+                o.set_timer(RetryingRegisterTimer::Resend, model_timeout());
+                *state = Cow::Owned(S::Client {
+                    awaiting: Some(*awaiting),
+                    op_count: *op_count,
+                    server,
+                });
+            }
+            (
+                A::Server(server_actor),
+                S::Server(server_state),
+                RetryingRegisterTimer::Server(timer),
+            ) => {
+                let mut server_state = Cow::Borrowed(server_state);
+                let mut server_out = Out::new();
+                server_actor.on_timeout(id, &mut server_state, timer, &mut server_out);
+                if let Cow::Owned(server_state) = server_state {
+                    *state = Cow::Owned(S::Server(server_state));
+                }
+                wrap_server_out(server_out, o);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Forwards a server's commands, wrapping its timers.
+fn wrap_server_out<ServerActor, InternalMsg>(
+    server_out: Out<ServerActor>,
+    o: &mut Out<RetryingRegisterActor<ServerActor>>)
+where
+    ServerActor: Actor<Msg = RegisterMsg<RequestId, char, InternalMsg>>,
+    InternalMsg: Clone + Debug + Eq + Hash,
+{
+    for command in server_out {
+        match command {
+            Command::Send(dst, msg) => o.send(dst, msg),
+            Command::SetTimer(timer, duration) => {
+                o.set_timer(RetryingRegisterTimer::Server(timer), duration);
+            }
+            Command::CancelTimer(timer) => {
+                o.cancel_timer(RetryingRegisterTimer::Server(timer));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::*;
+
+    #[test]
+    fn client_without_puts_is_idle() {
+        let checker = ActorModel::new((), ())
+            .actor(RetryingRegisterActor::Server(TestServer))
+            .actor(RetryingRegisterActor::Client { put_count: 0, server_count: 1 })
+            .checker().spawn_bfs().join();
+        assert_eq!(checker.unique_state_count(), 1);
+    }
+
+    #[test]
+    fn retries_after_drop() {
+        let checker = ActorModel::new((), ())
+            .lossy_network(LossyNetwork::Yes)
+            .actor(RetryingRegisterActor::Server(TestServer))
+            .actor(RetryingRegisterActor::Server(TestServer))
+            .actor(RetryingRegisterActor::Client { put_count: 1, server_count: 2 })
+            .property(Expectation::Sometimes, "written by next server after drop", |_, state| {
+                // The client first sends its `Put` to server 0.
+                let to_server_0 = state.network.iter_deliverable()
+                    .any(|e| e.dst == Id::from(0) && matches!(e.msg, RegisterMsg::Put(..)));
+                !to_server_0
+                    && *state.actor_states[0] == RetryingRegisterActorState::Server('?')
+                    && *state.actor_states[1] == RetryingRegisterActorState::Server('A')
+            })
+            .property(Expectation::Sometimes, "value read", |_, state| {
+                state.network.iter_deliverable()
+                    .any(|e| matches!(e.msg, RegisterMsg::GetOk(_, 'A')))
+            })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 102);
+    }
+
+    /// A register that is not replicated, which suffices to exercise the client.
+    #[derive(Clone)]
+    struct TestServer;
+
+    impl Actor for TestServer {
+        type Msg = RegisterMsg<RequestId, char, ()>;
+        type State = char;
+        type Timer = ();
+
+        fn on_start(&self, _id: Id, _o: &mut Out<Self>) -> Self::State {
+            '?'
+        }
+
+        fn on_msg(&self, _id: Id, state: &mut Cow<Self::State>,
+                  src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+            match msg {
+                RegisterMsg::Put(req_id, value) => {
+                    *state.to_mut() = value;
+                    o.send(src, RegisterMsg::PutOk(req_id));
+                }
+                RegisterMsg::Get(req_id) => {
+                    o.send(src, RegisterMsg::GetOk(req_id, **state));
+                }
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! A corrected variant of the replication protocol in `main.rs`. Any server accepts requests, but
+//! only a designated primary serves them: the other servers forward requests to the primary, which
+//! replies to the client directly. The primary therefore holds the only copy of the register that
+//! is ever read, while replication merely keeps the backups' copies up to date.
+//!
+//! Note that the primary is a single point of failure, so this variant does not tolerate crashes.
+
+use serde::{Deserialize, Serialize};
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::net::{SocketAddrV4, Ipv4Addr};
+
+#[derive(Clone)]
+struct ServerActor {
+    peers: Vec<Id>,
+    primary: Id,
+}
+
+type RequestId = u64;
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Deserialize, Serialize)]
+enum InternalMsg {
+    Replicate(RequestId, char),
+    ReplicateOk(RequestId),
+    ForwardPut(Id, RequestId, char), // includes the client's ID
+    ForwardGet(Id, RequestId),
+}
+
+#[derive(Clone, Debug, Hash, PartialEq)]
+struct ActorState {
+    value: char,
+    delivered: BTreeSet<(Id, RequestId)>,
+    in_flight_put: Option<PutState>,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq)]
+struct PutState {
+    req_id: RequestId,
+    src: Id,
+    peer_acks: BTreeSet<Id>,
+}
+
+impl Actor for ServerActor {
+    type Msg = RegisterMsg<RequestId, char, InternalMsg>;
+    type State = ActorState;
+    type Timer = ();
+
+    fn on_start(&self, _id: Id, _o: &mut Out<Self>) -> Self::State {
+        ActorState {
+            value: '?',
+            delivered: Default::default(),
+            in_flight_put: None,
+        }
+    }
+
+    fn on_msg(&self, id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        // Backups forward requests to the primary, and the primary treats a forwarded request
+        // like one received directly from the client.
+        let (src, msg) = match msg {
+            RegisterMsg::Put(req_id, value) if id != self.primary => {
+                o.send(self.primary,
+                       RegisterMsg::Internal(InternalMsg::ForwardPut(src, req_id, value)));
+                return;
+            }
+            RegisterMsg::Get(req_id) if id != self.primary => {
+                o.send(self.primary,
+                       RegisterMsg::Internal(InternalMsg::ForwardGet(src, req_id)));
+                return;
+            }
+            RegisterMsg::Internal(InternalMsg::ForwardPut(client, req_id, value)) => {
+                (client, RegisterMsg::Put(req_id, value))
+            }
+            RegisterMsg::Internal(InternalMsg::ForwardGet(client, req_id)) => {
+                (client, RegisterMsg::Get(req_id))
+            }
+            msg => (src, msg),
+        };
+
+        match msg {
+            RegisterMsg::Put(req_id, value) if state.in_flight_put.is_none() => {
+                if state.delivered.contains(&(src, req_id)) { return }
+
+                let state = state.to_mut();
+                state.value = value;
+                state.delivered.insert((src, req_id));
+                state.in_flight_put = Some(PutState {
+                    req_id,
+                    src,
+                    peer_acks: Default::default(),
+                });
+                for &peer_id in &self.peers {
+                    o.send(peer_id,
+                           RegisterMsg::Internal(
+                               InternalMsg::Replicate(req_id, value)));
+                }
+                // Will not reply w/ `PutOk` until all replicas ack.
+            }
+            RegisterMsg::Get(req_id) => {
+                o.send(src, RegisterMsg::GetOk(req_id, state.value));
+            }
+            RegisterMsg::Internal(InternalMsg::Replicate(req_id, value)) => {
+                if state.delivered.contains(&(src, req_id)) { return }
+
+                let state = state.to_mut();
+                state.value = value;
+                state.delivered.insert((src, req_id));
+                o.send(src,
+                       RegisterMsg::Internal(InternalMsg::ReplicateOk(req_id)));
+            }
+            RegisterMsg::Internal(InternalMsg::ReplicateOk(req_id)) => {
+                let state = state.to_mut();
+                if let Some(put) = &mut state.in_flight_put {
+                    if req_id != put.req_id { return }
+
+                    put.peer_acks.insert(src);
+                    if put.peer_acks.len() == self.peers.len() {
+                        o.send(put.src, RegisterMsg::PutOk(req_id));
+                        state.in_flight_put = None;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Running the program spawns actors on UDP ports 3000-3002, with the first serving as the
+// primary. Messages are JSON-serialized.
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    let id0 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000));
+    let id1 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3001));
+    let id2 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3002));
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (id0, ServerActor { peers: vec![id1, id2], primary: id0 }),
+            (id1, ServerActor { peers: vec![id0, id2], primary: id0 }),
+            (id2, ServerActor { peers: vec![id0, id1], primary: id0 }),
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+
+    #[test]
+    fn is_linearizable() {
+        base_model()
+            .actor(RegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![1]),
+                primary: Id::from(0),
+            }))
+            .actor(RegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![0]),
+                primary: Id::from(0),
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().spawn_dfs().join()
+            .assert_properties();
+
+        // The configuration for which `main.rs` fails.
+        base_model()
+            .actor(RegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![1]),
+                primary: Id::from(0),
+            }))
+            .actor(RegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![0]),
+                primary: Id::from(0),
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .checker().spawn_dfs().join()
+            .assert_properties();
+    }
+
+    fn base_model()
+        -> ActorModel<
+            RegisterActor<ServerActor>,
+            (),
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        ActorModel::new(
+                (),
+                LinearizabilityTester::new(Register('?'))
+            )
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.serialized_history().is_some()
+            })
+            .property(Expectation::Sometimes, "value chosen", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    if let RegisterMsg::GetOk(_, value) = e.msg {
+                        return *value != '?';
+                    }
+                    false
+                })
+            })
+            .record_msg_in(RegisterMsg::record_returns)
+            .record_msg_out(RegisterMsg::record_invocations)
+    }
+}
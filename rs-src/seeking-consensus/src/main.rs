@@ -1,3 +1,5 @@
+// `main` follows the tests, in the order that the book presents them.
+#![allow(clippy::items_after_test_module)]
 /* ANCHOR: all */
 
 use serde::{Deserialize, Serialize};
@@ -10,6 +12,25 @@ use std::net::{SocketAddrV4, Ipv4Addr};
 #[derive(Clone)]
 struct ServerActor {
     peers: Vec<Id>,
+    ack_quorum: AckQuorum,
+}
+
+/// Indicates how many peers must acknowledge a `Put` before the server replies.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum AckQuorum {
+    /// Await every peer, so a single crashed peer blocks every write.
+    AllPeers,
+    /// Await the specified number of peers, so the remaining peers may crash.
+    Peers(usize),
+}
+
+impl AckQuorum {
+    fn required(self, peer_count: usize) -> usize {
+        match self {
+            AckQuorum::AllPeers => peer_count,
+            AckQuorum::Peers(count) => count,
+        }
+    }
 }
 // ANCHOR_END: actor
 
@@ -60,20 +81,25 @@ impl Actor for ServerActor {
             RegisterMsg::Put(req_id, value) if state.in_flight_put.is_none() => {
                 if state.delivered.contains(&(src, req_id)) { return }
 
-                let mut state = state.to_mut();
+                let state = state.to_mut();
                 state.value = value;
                 state.delivered.insert((src, req_id));
-                state.in_flight_put = Some(PutState {
-                    req_id,
-                    src,
-                    peer_acks: Default::default(),
-                });
                 for &peer_id in &self.peers {
                     o.send(peer_id,
                            RegisterMsg::Internal(
                                InternalMsg::Replicate(req_id, value)));
                 }
-                // Will not reply w/ `PutOk` until all replicas ack.
+                if self.ack_quorum.required(self.peers.len()) == 0 {
+                    // No acks will arrive to complete the write, so it is already complete.
+                    o.send(src, RegisterMsg::PutOk(req_id));
+                    return;
+                }
+                state.in_flight_put = Some(PutState {
+                    req_id,
+                    src,
+                    peer_acks: Default::default(),
+                });
+                // Will not reply w/ `PutOk` until enough replicas ack.
             }
             RegisterMsg::Get(req_id) => {
                 o.send(src, RegisterMsg::GetOk(req_id, state.value));
@@ -81,7 +107,7 @@ impl Actor for ServerActor {
             RegisterMsg::Internal(InternalMsg::Replicate(req_id, value)) => {
                 if state.delivered.contains(&(src, req_id)) { return }
 
-                let mut state = state.to_mut();
+                let state = state.to_mut();
                 state.value = value;
                 state.delivered.insert((src, req_id));
                 o.send(src,
@@ -90,12 +116,12 @@ impl Actor for ServerActor {
             RegisterMsg::Internal(InternalMsg::ReplicateOk(req_id)) => {
                 if state.delivered.contains(&(src, req_id)) { return }
 
-                let mut state = state.to_mut();
+                let state = state.to_mut();
                 if let Some(put) = &mut state.in_flight_put {
                     if req_id != put.req_id { return }
 
                     put.peer_acks.insert(src);
-                    if put.peer_acks.len() == self.peers.len() {
+                    if put.peer_acks.len() == self.ack_quorum.required(self.peers.len()) {
                         o.send(put.src, RegisterMsg::PutOk(req_id));
                         state.in_flight_put = None;
                     }
@@ -110,6 +136,7 @@ impl Actor for ServerActor {
 #[cfg(test)]
 mod test {
     use super::*;
+    use retrying_client::*;
     use stateright::{*, semantics::*, semantics::register::*};
     use ActorModelAction::Deliver;
     use InternalMsg::{Replicate, ReplicateOk};
@@ -122,9 +149,11 @@ mod test {
         base_model()
             .actor(RegisterActor::Server(ServerActor {
                 peers: Id::vec_from(vec![1]),
+                ack_quorum: AckQuorum::AllPeers,
             }))
             .actor(RegisterActor::Server(ServerActor {
                 peers: Id::vec_from(vec![0]),
+                ack_quorum: AckQuorum::AllPeers,
             }))
             .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
             .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
@@ -138,9 +167,11 @@ mod test {
         let checker = base_model()
             .actor(RegisterActor::Server(ServerActor {
                 peers: Id::vec_from(vec![1]),
+                ack_quorum: AckQuorum::AllPeers,
             }))
             .actor(RegisterActor::Server(ServerActor {
                 peers: Id::vec_from(vec![0]),
+                ack_quorum: AckQuorum::AllPeers,
             }))
             .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
             .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
@@ -166,6 +197,97 @@ mod test {
         ]);
     }
 
+    #[test]
+    fn value_chosen_despite_lossy_network() {
+        // The client sends its `Get` to server 1, so a reply from server 0 requires a retry.
+        let checker = ActorModel::new((), ())
+            .lossy_network(LossyNetwork::Yes)
+            .actor(RetryingRegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![1]),
+                ack_quorum: AckQuorum::AllPeers,
+            }))
+            .actor(RetryingRegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![0]),
+                ack_quorum: AckQuorum::AllPeers,
+            }))
+            .actor(RetryingRegisterActor::Client { put_count: 1, server_count: 2 })
+            .property(Expectation::Sometimes, "value chosen after retry", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    matches!(e.msg, GetOk(_, value) if *value != '?') && e.src == Id::from(0)
+                })
+            })
+            .checker().spawn_dfs().join();
+        checker.assert_properties();
+    }
+
+    #[test]
+    fn quorum_acks_tolerate_crashes() {
+        // Awaiting every peer means that no write completes once a peer has crashed.
+        let checker = crash_model(AckQuorum::AllPeers).checker().spawn_dfs().join();
+        assert!(checker.discovery("value chosen").is_none());
+        assert_eq!(checker.unique_state_count(), 39);
+
+        // Awaiting a majority lets the remaining servers complete writes, and with a single
+        // surviving peer they behave as if there had never been a third server.
+        let checker = crash_model(AckQuorum::Peers(1)).checker().spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 795);
+
+        // But a server outside the quorum can serve a stale read, even without crashes.
+        let checker = base_model()
+            .actor(RegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![1, 2]),
+                ack_quorum: AckQuorum::Peers(1),
+            }))
+            .actor(RegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![0, 2]),
+                ack_quorum: AckQuorum::Peers(1),
+            }))
+            .actor(RegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![0, 1]),
+                ack_quorum: AckQuorum::Peers(1),
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 3 })
+            .checker().spawn_dfs().join();
+        checker.assert_discovery("linearizable", vec![
+            Deliver { src: Id::from(3), dst: Id::from(0), msg: Put(3, 'A') },
+            Deliver { src: Id::from(0), dst: Id::from(2), msg: Internal(Replicate(3, 'A')) },
+            Deliver { src: Id::from(2), dst: Id::from(0), msg: Internal(ReplicateOk(3)) },
+            Deliver { src: Id::from(0), dst: Id::from(3), msg: PutOk(3) },
+            Deliver { src: Id::from(3), dst: Id::from(1), msg: Get(6) },
+            Deliver { src: Id::from(1), dst: Id::from(3), msg: GetOk(6, '?') },
+        ]);
+    }
+
+    #[test]
+    fn writes_complete_without_required_acks() {
+        // Replies immediately rather than awaiting acks that will never complete the write.
+        let checker = crash_model(AckQuorum::Peers(0)).checker().spawn_dfs().join();
+        assert!(checker.discovery("value chosen").is_some());
+    }
+
+    /// Two servers whose third peer (`Id(4)`) has crashed. The crashed server is omitted from the
+    /// model rather than using `max_crashes`, as the checker does not distinguish a crashed actor's
+    /// state from the state before the crash, so crashes are otherwise never explored.
+    fn crash_model(ack_quorum: AckQuorum)
+        -> ActorModel<
+            RegisterActor<ServerActor>,
+            (),
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        base_model()
+            .actor(RegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![1, 4]),
+                ack_quorum,
+            }))
+            .actor(RegisterActor::Server(ServerActor {
+                peers: Id::vec_from(vec![0, 4]),
+                ack_quorum,
+            }))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 2 })
+    }
+
     fn base_model()
         -> ActorModel<
             RegisterActor<ServerActor>,
@@ -184,7 +306,7 @@ mod test {
                     if let RegisterMsg::GetOk(_, value) = e.msg {
                         return *value != '?';
                     }
-                    return false
+                    false
                 })
             })
             .record_msg_in(RegisterMsg::record_returns)
@@ -193,9 +315,14 @@ mod test {
     // ANCHOR_END: test
 }
 
-// Running the program spawns actors on UDP ports 3000-3002. Messages are JSON-serialized.
+// Running the program spawns actors on UDP ports 3000-3002. Messages are JSON-serialized. Pass
+// `quorum` as an argument to complete writes once a majority of servers have the value.
 fn main() {
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    let ack_quorum = match std::env::args().nth(1).as_deref() {
+        Some("quorum") => AckQuorum::Peers(1),
+        _ => AckQuorum::AllPeers,
+    };
     let id0 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000));
     let id1 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3001));
     let id2 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3002));
@@ -203,9 +330,18 @@ fn main() {
         serde_json::to_vec,
         |bytes| serde_json::from_slice(bytes),
         vec![
-            (id0, ServerActor { peers: vec![id1, id2].into_iter().collect() } ),
-            (id1, ServerActor { peers: vec![id0, id2].into_iter().collect() } ),
-            (id2, ServerActor { peers: vec![id0, id1].into_iter().collect() } ),
+            (id0, ServerActor {
+                peers: vec![id1, id2].into_iter().collect(),
+                ack_quorum,
+            }),
+            (id1, ServerActor {
+                peers: vec![id0, id2].into_iter().collect(),
+                ack_quorum,
+            }),
+            (id2, ServerActor {
+                peers: vec![id0, id1].into_iter().collect(),
+                ack_quorum,
+            }),
         ]).unwrap();
 }
 
@@ -0,0 +1,198 @@
+//! A variant of the server in `main.rs` that implements the compaction exercise. Rather than
+//! recording every delivered request ID, the server only records the highest request ID delivered
+//! by each client, which requires that each client's request IDs are monotonic. A redelivered or
+//! late `Put` has a request ID no larger than this "high-water mark" and is ignored.
+
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::net::{SocketAddrV4, Ipv4Addr};
+
+#[derive(Clone)]
+struct ServerActor;
+
+type RequestId = u64;
+
+#[derive(Clone, Debug, Hash, PartialEq)]
+struct ActorState {
+    value: char,
+    delivered: BTreeMap<Id, RequestId>, // highest request ID delivered per client
+}
+
+impl Actor for ServerActor {
+    type Msg = RegisterMsg<RequestId, char, ()>;
+    type State = ActorState;
+    type Timer = ();
+
+    fn on_start(&self, _id: Id, _o: &mut Out<Self>) -> Self::State {
+        ActorState {
+            value: '?',
+            delivered: Default::default(),
+        }
+    }
+
+    fn on_msg(&self, _id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        match msg {
+            RegisterMsg::Put(req_id, value) => {
+                if state.delivered.get(&src).is_some_and(|max| req_id <= *max) { return }
+
+                let state = state.to_mut();
+                state.value = value;
+                state.delivered.insert(src, req_id);
+                o.send(src, RegisterMsg::PutOk(req_id));
+            }
+            RegisterMsg::Get(req_id) => {
+                o.send(src, RegisterMsg::GetOk(req_id, state.value));
+            }
+            _ => {}
+        }
+    }
+}
+
+// Running the program spawns a single actor on UDP port 3000. Messages are JSON-serialized.
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000), ServerActor)
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn satisfies_all_properties() {
+        // These are the same state counts as for `main.rs`. Each `RegisterActor` client awaits a
+        // reply before sending its next request, so the request IDs delivered from a client are
+        // always a prefix of its requests, which the highest one identifies.
+        let checker = base_model()
+            .actor(RegisterActor::Server(ServerActor))
+            .actor(RegisterActor::Client { put_count: 2, server_count: 1 })
+            .checker().spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 8);
+
+        let checker = base_model()
+            .actor(RegisterActor::Server(ServerActor))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 1 })
+            .actor(RegisterActor::Client { put_count: 1, server_count: 1 })
+            .checker().spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 261);
+    }
+
+    #[test]
+    fn merges_states_of_pipelined_requests() {
+        // Client 1 sends its second `Put` without awaiting a reply to the first. If the second is
+        // delivered first, then the compacted server drops the first, whereas the server from
+        // `main.rs` applies it late, which yields states that compaction never reaches.
+        let compacted = pipelined_model(ServerActor).checker().spawn_dfs().join();
+        let uncompacted = pipelined_model(UncompactedServerActor).checker().spawn_dfs().join();
+        assert_eq!(compacted.unique_state_count(), 4);
+        assert!(compacted.unique_state_count() < uncompacted.unique_state_count());
+    }
+
+    fn pipelined_model<A>(server: A) -> ActorModel<A>
+    where A: Actor<Msg = RegisterMsg<RequestId, char, ()>>
+    {
+        ActorModel::new((), ())
+            .init_network(Network::new_unordered_duplicating([
+                Envelope { src: Id::from(1), dst: Id::from(0), msg: RegisterMsg::Put(1, 'A') },
+                Envelope { src: Id::from(1), dst: Id::from(0), msg: RegisterMsg::Put(2, 'B') },
+            ]))
+            // Without a property to check, the checker would stop after the initial state.
+            .property(Expectation::Always, "no unexpected replies", |_, state| {
+                state.network.iter_deliverable().all(|e| match e.msg {
+                    RegisterMsg::Put(..) => true,
+                    RegisterMsg::PutOk(req_id) => *req_id == 1 || *req_id == 2,
+                    _ => false,
+                })
+            })
+            .actor(server)
+    }
+
+    /// The server from `main.rs`, which records every delivered request ID.
+    #[derive(Clone)]
+    struct UncompactedServerActor;
+
+    #[derive(Clone, Debug, Hash, PartialEq)]
+    struct UncompactedState {
+        value: char,
+        delivered: BTreeSet<(Id, RequestId)>,
+    }
+
+    impl Actor for UncompactedServerActor {
+        type Msg = RegisterMsg<RequestId, char, ()>;
+        type State = UncompactedState;
+        type Timer = ();
+
+        fn on_start(&self, _id: Id, _o: &mut Out<Self>) -> Self::State {
+            UncompactedState {
+                value: '?',
+                delivered: Default::default(),
+            }
+        }
+
+        fn on_msg(&self, _id: Id, state: &mut Cow<Self::State>,
+                  src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+            match msg {
+                RegisterMsg::Put(req_id, value) => {
+                    if state.delivered.contains(&(src, req_id)) { return }
+
+                    let state = state.to_mut();
+                    state.value = value;
+                    state.delivered.insert((src, req_id));
+                    o.send(src, RegisterMsg::PutOk(req_id));
+                }
+                RegisterMsg::Get(req_id) => {
+                    o.send(src, RegisterMsg::GetOk(req_id, state.value));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn base_model()
+        -> ActorModel<
+            RegisterActor<ServerActor>,
+            (),
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        ActorModel::new(
+                (),
+                LinearizabilityTester::new(Register('?'))
+            )
+            .init_network(Network::new_unordered_duplicating([]))
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.serialized_history().is_some()
+            })
+            .property(Expectation::Always, "one request ID per client", |model, state| {
+                let client_count = model.actors.iter()
+                    .filter(|a| matches!(a, RegisterActor::Client { .. }))
+                    .count();
+                state.actor_states.iter().all(|s| match &**s {
+                    RegisterActorState::Server(ActorState { delivered, .. }) => {
+                        delivered.len() <= client_count
+                    }
+                    _ => true,
+                })
+            })
+            .property(Expectation::Sometimes, "get succeeds", |_, state| {
+                state.network.iter_deliverable()
+                    .any(|e| matches!(e.msg, RegisterMsg::GetOk(_, _)))
+            })
+            .property(Expectation::Sometimes, "put succeeds", |_, state| {
+                state.network.iter_deliverable()
+                    .any(|e| matches!(e.msg, RegisterMsg::PutOk(_)))
+            })
+            .record_msg_in(RegisterMsg::record_returns)
+            .record_msg_out(RegisterMsg::record_invocations)
+    }
+}
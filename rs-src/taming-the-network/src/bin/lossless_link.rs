@@ -0,0 +1,245 @@
+//! Implements the lossless link exercise. The client buffers each request until the server
+//! acknowledges it, resending the request whenever a timer fires first, so a request is eventually
+//! delivered as long as the network does not drop every copy. The server deduplicates requests as
+//! in `main.rs`, which ensures that a resent `Put` is not applied twice.
+//!
+//! The server cannot be the `ServerActor` of `main.rs` unchanged, however, as that server ignores a
+//! redelivered `Put`. If its `PutOk` were lost, then the client would resend the `Put` forever
+//! without ever being acknowledged, so this server acknowledges redeliveries again.
+
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{SocketAddrV4, Ipv4Addr};
+
+type RequestId = u64;
+
+#[derive(Clone)]
+enum LinkActor {
+    /// Puts each value in turn and then sends a `Get`, awaiting each reply.
+    Client { puts: Vec<char>, server_id: Id },
+    Server,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq)]
+enum LinkActorState {
+    Client {
+        op_count: usize,
+        unacked: BTreeMap<RequestId, RegisterMsg<RequestId, char, ()>>,
+    },
+    Server {
+        value: char,
+        delivered: BTreeSet<(Id, RequestId)>,
+    },
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum LinkTimer {
+    /// Indicates that a request may have been lost.
+    Resend(RequestId),
+}
+
+impl LinkActor {
+    /// Sends the client's next request (if any) and buffers it until acknowledged.
+    fn send_next(puts: &[char], server_id: Id, op_count: usize,
+                 unacked: &mut BTreeMap<RequestId, RegisterMsg<RequestId, char, ()>>,
+                 o: &mut Out<Self>) {
+        let req_id = op_count as RequestId + 1;
+        let msg = match op_count.cmp(&puts.len()) {
+            std::cmp::Ordering::Less => RegisterMsg::Put(req_id, puts[op_count]),
+            std::cmp::Ordering::Equal => RegisterMsg::Get(req_id),
+            std::cmp::Ordering::Greater => return,
+        };
+        o.send(server_id, msg.clone());
+        o.set_timer(LinkTimer::Resend(req_id), model_timeout());
+        unacked.insert(req_id, msg);
+    }
+}
+
+impl Actor for LinkActor {
+    type Msg = RegisterMsg<RequestId, char, ()>;
+    type State = LinkActorState;
+    type Timer = LinkTimer;
+
+    fn on_start(&self, _id: Id, o: &mut Out<Self>) -> Self::State {
+        match self {
+            LinkActor::Client { puts, server_id } => {
+                let mut unacked = BTreeMap::default();
+                LinkActor::send_next(puts, *server_id, 0, &mut unacked, o);
+                LinkActorState::Client { op_count: 0, unacked }
+            }
+            LinkActor::Server => {
+                LinkActorState::Server {
+                    value: '?',
+                    delivered: Default::default(),
+                }
+            }
+        }
+    }
+
+    fn on_msg(&self, _id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        match (self, &**state, msg) {
+            (
+                LinkActor::Client { puts, server_id },
+                LinkActorState::Client { op_count, unacked },
+                RegisterMsg::PutOk(req_id) | RegisterMsg::GetOk(req_id, _),
+            ) if unacked.contains_key(&req_id) => {
+                let mut unacked = unacked.clone();
+                unacked.remove(&req_id);
+                o.cancel_timer(LinkTimer::Resend(req_id));
+                let op_count = op_count + 1;
+                LinkActor::send_next(puts, *server_id, op_count, &mut unacked, o);
+                *state = Cow::Owned(LinkActorState::Client { op_count, unacked });
+            }
+            (
+                LinkActor::Server,
+                LinkActorState::Server { delivered, .. },
+                RegisterMsg::Put(req_id, value),
+            ) => {
+                // Unlike `main.rs`, a redelivery is acknowledged again because the client
+                // resends until it receives an acknowledgement, which may have been lost.
+                if delivered.contains(&(src, req_id)) {
+                    o.send(src, RegisterMsg::PutOk(req_id));
+                    return;
+                }
+
+                let mut delivered = delivered.clone();
+                delivered.insert((src, req_id));
+                *state = Cow::Owned(LinkActorState::Server { value, delivered });
+                o.send(src, RegisterMsg::PutOk(req_id));
+            }
+            (
+                LinkActor::Server,
+                LinkActorState::Server { value, .. },
+                RegisterMsg::Get(req_id),
+            ) => {
+                o.send(src, RegisterMsg::GetOk(req_id, *value));
+            }
+            _ => {}
+        }
+    }
+
+    fn on_timeout(&self, _id: Id, state: &mut Cow<Self::State>,
+                  timer: &Self::Timer, o: &mut Out<Self>) {
+        let LinkTimer::Resend(req_id) = timer;
+        if let (
+            LinkActor::Client { server_id, .. },
+            LinkActorState::Client { unacked, .. },
+        ) = (self, &**state) {
+            if let Some(msg) = unacked.get(req_id) {
+                o.send(*server_id, msg.clone());
+                o.set_timer(LinkTimer::Resend(*req_id), model_timeout());
+            }
+        }
+    }
+}
+
+// Running the program spawns a server on UDP port 3000 and a client on UDP port 3001 that writes
+// and then reads a value. Messages are JSON-serialized.
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    let server_id = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000));
+    let client_id = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3001));
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (server_id, LinkActor::Server),
+            (client_id, LinkActor::Client { puts: vec!['A'], server_id }),
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+
+    #[test]
+    fn satisfies_all_properties_despite_lossy_network() {
+        base_model()
+            .actor(LinkActor::Server)
+            .actor(LinkActor::Client { puts: vec!['A', 'B'], server_id: Id::from(0) })
+            .checker().spawn_dfs().join()
+            .assert_properties();
+
+        base_model()
+            .actor(LinkActor::Server)
+            .actor(LinkActor::Client { puts: vec!['A'], server_id: Id::from(0) })
+            .actor(LinkActor::Client { puts: vec!['B'], server_id: Id::from(0) })
+            .checker().spawn_dfs().join()
+            .assert_properties();
+    }
+
+    /// Records each request when first sent and each reply when first received, as resent
+    /// requests and reacknowledgements are indistinguishable from the originals.
+    #[derive(Clone, Debug, Hash, PartialEq)]
+    struct LinkHistory {
+        tester: LinearizabilityTester<Id, Register<char>>,
+        in_flight: BTreeMap<Id, RequestId>,
+    }
+
+    impl LinkHistory {
+        fn record_invocations(_cfg: &(), history: &Self,
+                              env: Envelope<&RegisterMsg<RequestId, char, ()>>) -> Option<Self> {
+            let (req_id, op) = match env.msg {
+                RegisterMsg::Put(req_id, value) => (*req_id, RegisterOp::Write(*value)),
+                RegisterMsg::Get(req_id) => (*req_id, RegisterOp::Read),
+                _ => return None,
+            };
+            if history.in_flight.get(&env.src) == Some(&req_id) { return None }
+
+            let mut history = history.clone();
+            let _ = history.tester.on_invoke(env.src, op);
+            history.in_flight.insert(env.src, req_id);
+            Some(history)
+        }
+
+        fn record_returns(_cfg: &(), history: &Self,
+                          env: Envelope<&RegisterMsg<RequestId, char, ()>>) -> Option<Self> {
+            let (req_id, ret) = match env.msg {
+                RegisterMsg::PutOk(req_id) => (*req_id, RegisterRet::WriteOk),
+                RegisterMsg::GetOk(req_id, value) => (*req_id, RegisterRet::ReadOk(*value)),
+                _ => return None,
+            };
+            if history.in_flight.get(&env.dst) != Some(&req_id) { return None }
+
+            let mut history = history.clone();
+            let _ = history.tester.on_return(env.dst, ret);
+            history.in_flight.remove(&env.dst);
+            Some(history)
+        }
+    }
+
+    fn base_model() -> ActorModel<LinkActor, (), LinkHistory> {
+        ActorModel::new(
+                (),
+                LinkHistory {
+                    tester: LinearizabilityTester::new(Register('?')),
+                    in_flight: Default::default(),
+                }
+            )
+            .init_network(Network::new_unordered_duplicating([]))
+            .lossy_network(LossyNetwork::Yes)
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.tester.serialized_history().is_some()
+            })
+            // Fails without resends, as a client would wait forever for a dropped request.
+            .property(Expectation::Eventually, "every client finishes", |_, state| {
+                state.actor_states.iter().all(|s| match &**s {
+                    LinkActorState::Client { unacked, .. } => unacked.is_empty(),
+                    LinkActorState::Server { .. } => true,
+                })
+            })
+            .property(Expectation::Sometimes, "get succeeds", |_, state| {
+                state.network.iter_deliverable()
+                    .any(|e| matches!(e.msg, RegisterMsg::GetOk(_, _)))
+            })
+            .property(Expectation::Sometimes, "put succeeds", |_, state| {
+                state.network.iter_deliverable()
+                    .any(|e| matches!(e.msg, RegisterMsg::PutOk(_)))
+            })
+            .record_msg_in(LinkHistory::record_returns)
+            .record_msg_out(LinkHistory::record_invocations)
+    }
+}
@@ -0,0 +1,155 @@
+//! A variant of the server in `main.rs` that implements the optimized compaction exercise. The
+//! server remembers a bounded "window" of the highest request IDs delivered by each client, so a
+//! request that arrives late is still served as long as it falls within the window. Requests below
+//! the window may be redeliveries of requests that were already forgotten and are ignored, as with
+//! the monotonic compaction of `compacted.rs` (which is equivalent to a window of size 1).
+
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{SocketAddrV4, Ipv4Addr};
+
+#[derive(Clone)]
+struct ServerActor {
+    window_size: usize,
+}
+
+type RequestId = u64;
+
+#[derive(Clone, Debug, Hash, PartialEq)]
+struct ActorState {
+    value: char,
+    delivered: BTreeMap<Id, BTreeSet<RequestId>>, // at most `window_size` per client
+}
+
+impl Actor for ServerActor {
+    type Msg = RegisterMsg<RequestId, char, ()>;
+    type State = ActorState;
+    type Timer = ();
+
+    fn on_start(&self, _id: Id, _o: &mut Out<Self>) -> Self::State {
+        ActorState {
+            value: '?',
+            delivered: Default::default(),
+        }
+    }
+
+    fn on_msg(&self, _id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        match msg {
+            RegisterMsg::Put(req_id, value) => {
+                if let Some(window) = state.delivered.get(&src) {
+                    if window.contains(&req_id) { return }
+                    if window.len() == self.window_size
+                        && window.first().is_some_and(|min| req_id < *min) { return }
+                }
+
+                let state = state.to_mut();
+                state.value = value;
+                let window = state.delivered.entry(src).or_default();
+                window.insert(req_id);
+                if window.len() > self.window_size {
+                    window.pop_first();
+                }
+                o.send(src, RegisterMsg::PutOk(req_id));
+            }
+            RegisterMsg::Get(req_id) => {
+                o.send(src, RegisterMsg::GetOk(req_id, state.value));
+            }
+            _ => {}
+        }
+    }
+}
+
+// Running the program spawns a single actor on UDP port 3000. Messages are JSON-serialized.
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000), ServerActor { window_size: 16 })
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+    use RegisterMsg::{Put, PutOk};
+
+    #[test]
+    fn satisfies_all_properties() {
+        for network in [
+            Network::new_unordered_nonduplicating([]),
+            Network::new_unordered_duplicating([]),
+        ] {
+            base_model()
+                .init_network(network.clone())
+                .actor(RegisterActor::Server(ServerActor { window_size: 2 }))
+                .actor(RegisterActor::Client { put_count: 2, server_count: 1 })
+                .checker().spawn_dfs().join()
+                .assert_properties();
+
+            base_model()
+                .init_network(network)
+                .actor(RegisterActor::Server(ServerActor { window_size: 2 }))
+                .actor(RegisterActor::Client { put_count: 1, server_count: 1 })
+                .actor(RegisterActor::Client { put_count: 1, server_count: 1 })
+                .checker().spawn_dfs().join()
+                .assert_properties();
+        }
+    }
+
+    #[test]
+    fn serves_reordered_requests_within_window() {
+        // `RegisterActor` clients only have one request in flight, so the client is idle and the
+        // network instead starts with several of its requests in flight.
+        let pipelined_model = |window_size| {
+            ActorModel::new((), ())
+                .init_network(Network::new_unordered_duplicating([
+                    Envelope { src: Id::from(1), dst: Id::from(0), msg: Put(1, 'A') },
+                    Envelope { src: Id::from(1), dst: Id::from(0), msg: Put(2, 'B') },
+                    Envelope { src: Id::from(1), dst: Id::from(0), msg: Put(3, 'C') },
+                ]))
+                .actor(RegisterActor::Server(ServerActor { window_size }))
+                .actor(RegisterActor::Client { put_count: 0, server_count: 1 })
+                .property(Expectation::Eventually, "every put acknowledged", |_, state| {
+                    (1..=3).all(|req_id| {
+                        state.network.iter_deliverable().any(|e| e.msg == &PutOk(req_id))
+                    })
+                })
+        };
+
+        pipelined_model(3).checker().spawn_dfs().join().assert_properties();
+
+        // A smaller window drops requests that arrive too late.
+        let checker = pipelined_model(1).checker().spawn_dfs().join();
+        assert!(checker.discovery("every put acknowledged").is_some());
+    }
+
+    fn base_model()
+        -> ActorModel<
+            RegisterActor<ServerActor>,
+            (),
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        ActorModel::new(
+                (),
+                LinearizabilityTester::new(Register('?'))
+            )
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.serialized_history().is_some()
+            })
+            .property(Expectation::Sometimes, "get succeeds", |_, state| {
+                state.network.iter_deliverable()
+                    .any(|e| matches!(e.msg, RegisterMsg::GetOk(_, _)))
+            })
+            .property(Expectation::Sometimes, "put succeeds", |_, state| {
+                state.network.iter_deliverable()
+                    .any(|e| matches!(e.msg, RegisterMsg::PutOk(_)))
+            })
+            .record_msg_in(RegisterMsg::record_returns)
+            .record_msg_out(RegisterMsg::record_invocations)
+    }
+}
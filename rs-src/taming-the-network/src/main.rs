@@ -1,3 +1,5 @@
+// `main` follows the tests, in the order that the book presents them.
+#![allow(clippy::items_after_test_module)]
 /* ANCHOR: all */
 
 use stateright::actor::{*, register::*};
@@ -35,7 +37,7 @@ impl Actor for ServerActor {
             RegisterMsg::Put(req_id, value) => {
                 if state.delivered.contains(&(src, req_id)) { return }
 
-                let mut state = state.to_mut();
+                let state = state.to_mut();
                 state.value = value;
                 state.delivered.insert((src, req_id));
                 o.send(src, RegisterMsg::PutOk(req_id));
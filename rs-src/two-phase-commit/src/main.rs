@@ -0,0 +1,229 @@
+//! Provides atomic commitment via the two-phase commit protocol as specified in "[Consensus on
+//! Transaction Commit](https://www.microsoft.com/en-us/research/wp-content/uploads/2016/02/tr-2003-96.pdf)"
+//! by Jim Gray and Leslie Lamport, which the "Comparison with TLA+" chapter checks as a pure
+//! `Model`. Here the transaction manager and resource managers are actors that exchange messages,
+//! so the same protocol can also run over UDP.
+//!
+//! Resource managers decide whether to prepare or to abort unilaterally, and the transaction
+//! manager commits once every resource manager has prepared, or aborts if it stops waiting first.
+
+/* ANCHOR: all */
+use serde::{Deserialize, Serialize};
+use stateright::actor::*;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::net::{SocketAddrV4, Ipv4Addr};
+use std::ops::Range;
+use std::time::Duration;
+
+// ANCHOR: actor-msg
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+enum TwoPhaseMsg {
+    Prepared,
+    Commit,
+    Abort,
+}
+// ANCHOR_END: actor-msg
+use TwoPhaseMsg::*;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum TwoPhaseTimer {
+    /// Prompts a resource manager to prepare.
+    Prepare,
+    /// Prompts a resource manager to abort unilaterally, unless it has already prepared.
+    ChooseToAbort,
+    /// Prompts the transaction manager to stop waiting for resource managers to prepare.
+    Abort,
+}
+
+const PREPARE_DELAY: Range<Duration> = Duration::from_secs(1)..Duration::from_secs(2);
+const CHOOSE_TO_ABORT_DELAY: Range<Duration> = Duration::from_secs(3)..Duration::from_secs(4);
+const TM_TIMEOUT: Range<Duration> = Duration::from_secs(5)..Duration::from_secs(6);
+
+// ANCHOR: actor-state
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum TwoPhaseState {
+    Tm { tm_state: TmState, tm_prepared: BTreeSet<Id> },
+    Rm(RmState),
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum TmState { Init, Committed, Aborted }
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum RmState { Working, Prepared, Committed, Aborted }
+// ANCHOR_END: actor-state
+
+// ANCHOR: actor
+#[derive(Clone)]
+enum TwoPhaseActor {
+    Tm { rms: Vec<Id> },
+    Rm { tm: Id },
+}
+
+impl Actor for TwoPhaseActor {
+    type Msg = TwoPhaseMsg;
+    type State = TwoPhaseState;
+    type Timer = TwoPhaseTimer;
+
+    fn on_start(&self, _id: Id, o: &mut Out<Self>) -> Self::State {
+        match self {
+            TwoPhaseActor::Tm { .. } => {
+                o.set_timer(TwoPhaseTimer::Abort, TM_TIMEOUT);
+                TwoPhaseState::Tm { tm_state: TmState::Init, tm_prepared: Default::default() }
+            }
+            TwoPhaseActor::Rm { .. } => {
+                // Whichever timer fires first determines the resource manager's vote.
+                o.set_timer(TwoPhaseTimer::Prepare, PREPARE_DELAY);
+                o.set_timer(TwoPhaseTimer::ChooseToAbort, CHOOSE_TO_ABORT_DELAY);
+                TwoPhaseState::Rm(RmState::Working)
+            }
+        }
+    }
+
+    fn on_msg(&self, _id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        match (self, state.as_ref(), msg) {
+            (TwoPhaseActor::Tm { rms },
+             TwoPhaseState::Tm { tm_state: TmState::Init, tm_prepared }, Prepared) => {
+                if !rms.contains(&src) { return }
+                if tm_prepared.contains(&src) { return }
+                let mut tm_prepared = tm_prepared.clone();
+                tm_prepared.insert(src);
+                let tm_state = if rms.iter().all(|rm| tm_prepared.contains(rm)) {
+                    o.cancel_timer(TwoPhaseTimer::Abort);
+                    o.broadcast(rms, &Commit);
+                    TmState::Committed
+                } else {
+                    TmState::Init
+                };
+                *state.to_mut() = TwoPhaseState::Tm { tm_state, tm_prepared };
+            }
+            // A decision is final, so only an undecided resource manager heeds one.
+            (TwoPhaseActor::Rm { .. },
+             TwoPhaseState::Rm(RmState::Working | RmState::Prepared), Commit) => {
+                *state.to_mut() = TwoPhaseState::Rm(RmState::Committed);
+            }
+            (TwoPhaseActor::Rm { .. },
+             TwoPhaseState::Rm(RmState::Working | RmState::Prepared), Abort) => {
+                *state.to_mut() = TwoPhaseState::Rm(RmState::Aborted);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_timeout(&self, _id: Id, state: &mut Cow<Self::State>,
+                  timer: &Self::Timer, o: &mut Out<Self>) {
+        match (self, state.as_ref(), timer) {
+            (TwoPhaseActor::Tm { rms },
+             TwoPhaseState::Tm { tm_state: TmState::Init, tm_prepared }, TwoPhaseTimer::Abort) => {
+                o.broadcast(rms, &Abort);
+                *state.to_mut() = TwoPhaseState::Tm {
+                    tm_state: TmState::Aborted,
+                    tm_prepared: tm_prepared.clone(),
+                };
+            }
+            (TwoPhaseActor::Rm { tm },
+             TwoPhaseState::Rm(RmState::Working), TwoPhaseTimer::Prepare) => {
+                o.cancel_timer(TwoPhaseTimer::ChooseToAbort);
+                o.send(*tm, Prepared);
+                *state.to_mut() = TwoPhaseState::Rm(RmState::Prepared);
+            }
+            (TwoPhaseActor::Rm { .. },
+             TwoPhaseState::Rm(RmState::Working), TwoPhaseTimer::ChooseToAbort) => {
+                // The transaction manager learns of the abort by timing out.
+                o.cancel_timer(TwoPhaseTimer::Prepare);
+                *state.to_mut() = TwoPhaseState::Rm(RmState::Aborted);
+            }
+            _ => {}
+        }
+    }
+}
+// ANCHOR_END: actor
+
+// Running the program spawns a transaction manager on UDP port 3000 and resource managers on
+// ports 3001-3002. Messages are JSON-serialized.
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    let tm = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000));
+    let rm1 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3001));
+    let rm2 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3002));
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (tm, TwoPhaseActor::Tm { rms: vec![rm1, rm2] }),
+            (rm1, TwoPhaseActor::Rm { tm }),
+            (rm2, TwoPhaseActor::Rm { tm }),
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::*;
+
+    // ANCHOR: test
+    #[test]
+    fn is_consistent_with_two_rms() {
+        let checker = two_phase_model(2)
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 132);
+    }
+
+    #[test]
+    fn is_consistent_with_three_rms() {
+        let checker = two_phase_model(3)
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 1_374);
+    }
+
+    #[test]
+    fn detects_conflicting_decisions() {
+        // As if a faulty transaction manager had also sent `Abort` to the first resource manager,
+        // which must not be able to disagree with the others.
+        let checker = two_phase_model(2)
+            .init_network(Network::new_unordered_duplicating([
+                Envelope { src: Id::from(0), dst: Id::from(1), msg: Abort },
+            ]))
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        assert!(checker.discovery("consistent").is_some());
+    }
+
+    fn rm_states(state: &ActorModelState<TwoPhaseActor>) -> impl Iterator<Item = RmState> + '_ {
+        state.actor_states.iter().filter_map(|s| match **s {
+            TwoPhaseState::Rm(rm_state) => Some(rm_state),
+            TwoPhaseState::Tm { .. } => None,
+        })
+    }
+
+    /// The transaction manager is actor 0, followed by the specified number of resource managers.
+    fn two_phase_model(rm_count: usize) -> ActorModel<TwoPhaseActor> {
+        ActorModel::new((), ())
+            .actor(TwoPhaseActor::Tm { rms: Id::vec_from(1..=rm_count) })
+            .actors((0..rm_count).map(|_| TwoPhaseActor::Rm { tm: Id::from(0) }))
+            .property(Expectation::Always, "consistent", |_, state| {
+                !(rm_states(state).any(|s| s == RmState::Aborted)
+                    && rm_states(state).any(|s| s == RmState::Committed))
+            })
+            .property(Expectation::Eventually, "every RM reaches Committed or Aborted",
+                      |_, state| {
+                rm_states(state).all(|s| s == RmState::Committed || s == RmState::Aborted)
+            })
+            .property(Expectation::Sometimes, "committed", |_, state| {
+                rm_states(state).all(|s| s == RmState::Committed)
+            })
+            .property(Expectation::Sometimes, "aborted after preparing", |_, state| {
+                matches!(*state.actor_states[0],
+                         TwoPhaseState::Tm { tm_state: TmState::Aborted, ref tm_prepared }
+                             if !tm_prepared.is_empty())
+                    && rm_states(state).all(|s| s == RmState::Aborted)
+            })
+    }
+    // ANCHOR_END: test
+}
+
+/* ANCHOR_END: all */
@@ -0,0 +1,575 @@
+//! Provides a linearizable register via Viewstamped Replication, as described in "[Viewstamped
+//! Replication Revisited](https://pmg.csail.mit.edu/papers/vr-revisited.pdf)" by Barbara Liskov
+//! and James Cowling. A primary orders client requests in a log and replicates them to backups. If
+//! the backups suspect that the primary has failed, they run a view change that installs the next
+//! replica as primary, and a replica that restarts without its state runs a recovery protocol
+//! before rejoining.
+//!
+//! The paper's client table is replaced by having the primary ignore requests that are already in
+//! its log. State transfer and reconfiguration are omitted, so a replica that misses messages from
+//! a view only catches up when the next view starts.
+
+/* ANCHOR: all */
+use serde::{Deserialize, Serialize};
+use stateright::actor::{*, register::*};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{SocketAddrV4, Ipv4Addr};
+use std::ops::Range;
+use std::time::Duration;
+
+// ANCHOR: actor-msg
+type RequestId = u64;
+type Value = char;
+type View = usize; // the primary is chosen round-robin, so view `v` is led by replica `v % n`
+type OpNum = usize; // starts at 1, so 0 precedes the first operation
+type Nonce = u64;
+type PrimaryState = (Vec<Command>, OpNum); // the log and commit number, sent during recovery
+
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+enum VrMsg {
+    Forward(Command), // a client request received by a backup
+
+    // normal operation
+    Prepare { view: View, op: OpNum, command: Command, commit: OpNum },
+    PrepareOk { view: View, op: OpNum },
+    Commit { view: View, commit: OpNum },
+
+    // view change
+    StartViewChange { view: View },
+    DoViewChange { view: View, log: Vec<Command>, last_normal_view: View, commit: OpNum },
+    StartView { view: View, log: Vec<Command>, commit: OpNum },
+
+    // recovery
+    Recovery { nonce: Nonce },
+    RecoveryResponse { view: View, nonce: Nonce, primary_state: Option<PrimaryState> },
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+enum Command {
+    Get(Id, RequestId),
+    Put(Id, RequestId, Value),
+}
+// ANCHOR_END: actor-msg
+use VrMsg::*;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum VrTimer {
+    /// Prompts the primary to reassure backups that it is available.
+    Heartbeat,
+    /// Indicates that a backup has not heard from the primary in a while, or that a view change
+    /// has stalled.
+    ViewChange,
+    /// Prompts a recovering replica to ask for the system's state again.
+    Recovery,
+}
+
+const HEARTBEAT_INTERVAL: Range<Duration> = Duration::from_millis(500)..Duration::from_millis(500);
+const VIEW_CHANGE_TIMEOUT: Range<Duration> = Duration::from_secs(2)..Duration::from_secs(3);
+const RECOVERY_INTERVAL: Range<Duration> = Duration::from_secs(1)..Duration::from_secs(1);
+
+// ANCHOR: actor-state
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct VrState {
+    view: View,
+    log: Vec<Command>, // the op number is the length of the log
+    commit: OpNum,
+    last_normal_view: View,
+    status: Status,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum Status {
+    Normal {
+        acks: BTreeMap<Id, OpNum>, // only used by the primary
+    },
+    ViewChange {
+        start_view_changes: BTreeSet<Id>,
+        do_view_changes: BTreeMap<Id, (View, Vec<Command>, OpNum)>, // only used by the new primary
+    },
+    Recovering {
+        nonce: Nonce,
+        responses: BTreeMap<Id, (View, Option<PrimaryState>)>,
+    },
+}
+// ANCHOR_END: actor-state
+
+// ANCHOR: actor
+#[derive(Clone)]
+struct VrActor {
+    replicas: Vec<Id>, // every replica, including this one, in the order they serve as primary
+    recovery_nonce: Option<Nonce>, // set when restarting a replica that has lost its state
+}
+
+impl VrActor {
+    fn primary(&self, view: View) -> Id {
+        self.replicas[view % self.replicas.len()]
+    }
+
+    fn peers(&self, id: Id) -> Vec<Id> {
+        self.replicas.iter().copied().filter(|r| *r != id).collect()
+    }
+
+    fn quorum(&self) -> usize {
+        majority(self.replicas.len())
+    }
+
+    fn set_timers(&self, id: Id, view: View, o: &mut Out<Self>) {
+        if id == self.primary(view) {
+            o.cancel_timer(VrTimer::ViewChange);
+            o.set_timer(VrTimer::Heartbeat, HEARTBEAT_INTERVAL);
+        } else {
+            o.cancel_timer(VrTimer::Heartbeat);
+            o.set_timer(VrTimer::ViewChange, VIEW_CHANGE_TIMEOUT);
+        }
+    }
+
+    /// Resumes normal operation in the specified view with the specified log.
+    fn start_normal(&self, id: Id, state: &mut VrState, view: View, log: Vec<Command>,
+                    commit: OpNum, o: &mut Out<Self>) {
+        state.view = view;
+        state.commit = state.commit.max(commit).min(log.len());
+        state.log = log;
+        state.last_normal_view = view;
+        state.status = Status::Normal { acks: Default::default() };
+        o.cancel_timer(VrTimer::Recovery);
+        self.set_timers(id, view, o);
+    }
+
+    /// Commits operations through `commit`, replying to clients if this replica is the primary.
+    fn commit(&self, id: Id, state: &mut VrState, commit: OpNum, o: &mut Out<Self>) {
+        if commit <= state.commit { return }
+
+        if id == self.primary(state.view) {
+            let mut value = value_at(&state.log[..state.commit]);
+            for command in &state.log[state.commit..commit] {
+                match *command {
+                    Command::Put(requester, req_id, v) => {
+                        value = v;
+                        o.send(requester, RegisterMsg::PutOk(req_id));
+                    }
+                    Command::Get(requester, req_id) => {
+                        o.send(requester, RegisterMsg::GetOk(req_id, value));
+                    }
+                }
+            }
+        }
+        state.commit = commit;
+    }
+
+    /// Commits through the last operation that a quorum (including the primary) has prepared.
+    fn advance_commit(&self, id: Id, state: &mut VrState, o: &mut Out<Self>) {
+        let commit = match &state.status {
+            Status::Normal { acks } => {
+                (state.commit + 1..=state.log.len()).rev().find(|&op| {
+                    1 + acks.values().filter(|a| **a >= op).count() >= self.quorum()
+                })
+            }
+            _ => None,
+        };
+        if let Some(commit) = commit {
+            self.commit(id, state, commit, o);
+        }
+    }
+
+    fn on_command(&self, id: Id, state: &mut Cow<VrState>, command: Command, forwarded: bool,
+                  o: &mut Out<Self>) {
+        if !matches!(state.status, Status::Normal { .. }) { return }
+
+        let primary = self.primary(state.view);
+        if id != primary {
+            if !forwarded {
+                o.send(primary, RegisterMsg::Internal(Forward(command)));
+            }
+            return;
+        }
+        if state.log.contains(&command) { return }
+
+        let state = state.to_mut();
+        state.log.push(command);
+        o.broadcast(&self.peers(id), &RegisterMsg::Internal(Prepare {
+            view: state.view,
+            op: state.log.len(),
+            command,
+            commit: state.commit,
+        }));
+        self.advance_commit(id, state, o); // in case there are no backups
+    }
+
+    /// Abandons the current view in favor of a later one.
+    fn start_view_change(&self, id: Id, state: &mut VrState, view: View, o: &mut Out<Self>) {
+        state.view = view;
+        state.status = Status::ViewChange {
+            start_view_changes: Default::default(),
+            do_view_changes: Default::default(),
+        };
+        o.cancel_timer(VrTimer::Heartbeat);
+        o.set_timer(VrTimer::ViewChange, VIEW_CHANGE_TIMEOUT);
+        o.broadcast(&self.peers(id), &RegisterMsg::Internal(StartViewChange { view }));
+        self.on_start_view_change(id, state, id, o);
+    }
+
+    /// Once a quorum agrees to change views, each of them sends its log to the new primary.
+    fn on_start_view_change(&self, id: Id, state: &mut VrState, src: Id, o: &mut Out<Self>) {
+        let reached_quorum = match &mut state.status {
+            Status::ViewChange { start_view_changes, .. } => {
+                start_view_changes.insert(src)
+                    && start_view_changes.len() == self.quorum()
+            }
+            _ => false,
+        };
+        if !reached_quorum { return }
+
+        let view = state.view;
+        let primary = self.primary(view);
+        if id == primary {
+            let log = state.log.clone();
+            let (last_normal_view, commit) = (state.last_normal_view, state.commit);
+            self.on_do_view_change(id, state, id, (last_normal_view, log, commit), o);
+        } else {
+            o.send(primary, RegisterMsg::Internal(DoViewChange {
+                view,
+                log: state.log.clone(),
+                last_normal_view: state.last_normal_view,
+                commit: state.commit,
+            }));
+        }
+    }
+
+    /// Once the new primary hears from a quorum, it adopts the most recent log among them, which
+    /// contains every committed operation.
+    fn on_do_view_change(&self, id: Id, state: &mut VrState, src: Id,
+                         do_view_change: (View, Vec<Command>, OpNum), o: &mut Out<Self>) {
+        let (log, commit) = match &mut state.status {
+            Status::ViewChange { do_view_changes, .. } => {
+                if do_view_changes.insert(src, do_view_change).is_some()
+                    || do_view_changes.len() != self.quorum() { return }
+
+                let (_, log, _) = do_view_changes.values()
+                    .max_by_key(|(last_normal_view, log, _)| (*last_normal_view, log.len()))
+                    .unwrap();
+                let commit = do_view_changes.values().map(|(_, _, commit)| *commit).max().unwrap();
+                (log.clone(), commit)
+            }
+            _ => return,
+        };
+
+        let view = state.view;
+        o.broadcast(&self.peers(id), &RegisterMsg::Internal(StartView {
+            view,
+            log: log.clone(),
+            commit,
+        }));
+        self.start_normal(id, state, view, log, commit, o);
+        self.advance_commit(id, state, o); // in case there are no backups
+    }
+}
+
+/// Returns the register's value after applying the specified operations.
+fn value_at(log: &[Command]) -> Value {
+    log.iter().rev()
+        .find_map(|command| match command {
+            Command::Put(_, _, value) => Some(*value),
+            Command::Get(_, _) => None,
+        })
+        .unwrap_or('?')
+}
+
+impl Actor for VrActor {
+    type Msg = RegisterMsg<RequestId, Value, VrMsg>;
+    type State = VrState;
+    type Timer = VrTimer;
+
+    fn on_start(&self, id: Id, o: &mut Out<Self>) -> Self::State {
+        let status = match self.recovery_nonce {
+            Some(nonce) => {
+                o.broadcast(&self.peers(id), &RegisterMsg::Internal(Recovery { nonce }));
+                o.set_timer(VrTimer::Recovery, RECOVERY_INTERVAL);
+                Status::Recovering { nonce, responses: Default::default() }
+            }
+            None => {
+                self.set_timers(id, 0, o);
+                Status::Normal { acks: Default::default() }
+            }
+        };
+        VrState {
+            view: 0,
+            log: Vec::new(),
+            commit: 0,
+            last_normal_view: 0,
+            status,
+        }
+    }
+
+    fn on_msg(&self, id: Id, state: &mut Cow<Self::State>,
+              src: Id, msg: Self::Msg, o: &mut Out<Self>) {
+        use RegisterMsg::*;
+        let is_normal = matches!(state.status, Status::Normal { .. });
+        let is_recovering = matches!(state.status, Status::Recovering { .. });
+        match msg {
+            Put(req_id, value) => {
+                self.on_command(id, state, Command::Put(src, req_id, value), false, o);
+            }
+            Get(req_id) => self.on_command(id, state, Command::Get(src, req_id), false, o),
+            Internal(Forward(command)) => self.on_command(id, state, command, true, o),
+
+            // A backup only accepts operations in order. Replicas that have fallen behind in
+            // an earlier view would request a state transfer, which is not implemented here.
+            Internal(Prepare { view, op, command, commit })
+                if is_normal && view == state.view && op <= state.log.len() + 1 =>
+            {
+                let state = state.to_mut();
+                if op == state.log.len() + 1 {
+                    state.log.push(command);
+                }
+                state.commit = state.commit.max(commit.min(op));
+                o.set_timer(VrTimer::ViewChange, VIEW_CHANGE_TIMEOUT);
+                o.send(src, Internal(PrepareOk { view, op }));
+            }
+            Internal(PrepareOk { view, op })
+                if is_normal && view == state.view && id == self.primary(view) =>
+            {
+                let state = state.to_mut();
+                if let Status::Normal { acks } = &mut state.status {
+                    let ack = acks.entry(src).or_default();
+                    *ack = (*ack).max(op);
+                }
+                self.advance_commit(id, state, o);
+            }
+            Internal(Commit { view, commit }) if is_normal && view == state.view => {
+                if commit > state.commit && commit <= state.log.len() {
+                    state.to_mut().commit = commit;
+                }
+                o.set_timer(VrTimer::ViewChange, VIEW_CHANGE_TIMEOUT);
+            }
+
+            Internal(StartViewChange { view }) if !is_recovering && view >= state.view => {
+                let state = state.to_mut();
+                if view > state.view {
+                    self.start_view_change(id, state, view, o);
+                }
+                self.on_start_view_change(id, state, src, o);
+            }
+            Internal(DoViewChange { view, log, last_normal_view, commit })
+                if !is_recovering && view >= state.view =>
+            {
+                let state = state.to_mut();
+                if view > state.view {
+                    self.start_view_change(id, state, view, o);
+                }
+                self.on_do_view_change(id, state, src, (last_normal_view, log, commit), o);
+            }
+            Internal(StartView { view, log, commit })
+                if !is_recovering && (view > state.view || view == state.view && !is_normal) =>
+            {
+                let state = state.to_mut();
+                self.start_normal(id, state, view, log, commit, o);
+                if state.log.len() > state.commit {
+                    o.send(src, Internal(PrepareOk { view, op: state.log.len() }));
+                }
+            }
+
+            Internal(Recovery { nonce }) if is_normal => {
+                let primary_state = if id == self.primary(state.view) {
+                    Some((state.log.clone(), state.commit))
+                } else {
+                    None
+                };
+                o.send(src, Internal(RecoveryResponse { view: state.view, nonce, primary_state }));
+            }
+            Internal(RecoveryResponse { view, nonce: n, primary_state })
+                if matches!(state.status, Status::Recovering { nonce, .. } if nonce == n) =>
+            {
+                // Recovery completes once a quorum responds, including the primary of the latest
+                // view among them.
+                let state = state.to_mut();
+                let recovered = match &mut state.status {
+                    Status::Recovering { responses, .. } => {
+                        responses.insert(src, (view, primary_state));
+                        if responses.len() < self.quorum() { return }
+                        let view = responses.values().map(|(view, _)| *view).max().unwrap();
+                        match responses.get(&self.primary(view)) {
+                            Some((v, Some((log, commit)))) if *v == view => {
+                                (view, log.clone(), *commit)
+                            }
+                            _ => return,
+                        }
+                    }
+                    _ => return,
+                };
+                let (view, log, commit) = recovered;
+                self.start_normal(id, state, view, log, commit, o);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_timeout(&self, id: Id, state: &mut Cow<Self::State>,
+                  timer: &Self::Timer, o: &mut Out<Self>) {
+        match (timer, &state.status) {
+            (VrTimer::Heartbeat, Status::Normal { .. }) if id == self.primary(state.view) => {
+                o.broadcast(&self.peers(id), &RegisterMsg::Internal(Commit {
+                    view: state.view,
+                    commit: state.commit,
+                }));
+                o.set_timer(VrTimer::Heartbeat, HEARTBEAT_INTERVAL);
+            }
+            (VrTimer::ViewChange, Status::Normal { .. } | Status::ViewChange { .. }) => {
+                // Backups suspect the primary, and any replica can give up on a stalled view
+                // change.
+                if matches!(state.status, Status::Normal { .. }) && id == self.primary(state.view) {
+                    return;
+                }
+                let view = state.view + 1;
+                self.start_view_change(id, state.to_mut(), view, o);
+            }
+            (VrTimer::Recovery, Status::Recovering { nonce, .. }) => {
+                o.broadcast(&self.peers(id), &RegisterMsg::Internal(Recovery { nonce: *nonce }));
+                o.set_timer(VrTimer::Recovery, RECOVERY_INTERVAL);
+            }
+            _ => {}
+        }
+    }
+}
+// ANCHOR_END: actor
+
+// Running the program spawns actors on UDP ports 3000-3002. Messages are JSON-serialized.
+fn main() {
+    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
+    let id0 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3000));
+    let id1 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3001));
+    let id2 = Id::from(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 3002));
+    let replicas = vec![id0, id1, id2];
+    spawn(
+        serde_json::to_vec,
+        |bytes| serde_json::from_slice(bytes),
+        vec![
+            (id0, VrActor { replicas: replicas.clone(), recovery_nonce: None }),
+            (id1, VrActor { replicas: replicas.clone(), recovery_nonce: None }),
+            (id2, VrActor { replicas, recovery_nonce: None }),
+        ]).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stateright::{*, semantics::*, semantics::register::*};
+
+    // ANCHOR: test
+    #[test]
+    fn is_linearizable_quick() {
+        let checker = base_model(0)
+            .actors(vr_actors(3, None))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 3 })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 5_478);
+    }
+
+    #[test]
+    fn recovers_lost_state() {
+        // The last replica restarts without its state, and it must recover before rejoining.
+        let checker = base_model(0)
+            .actors(vr_actors(3, Some(Id::from(2))))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 3 })
+            .property(Expectation::Sometimes, "recovered with a write", |_, state| {
+                match &*state.actor_states[2] {
+                    RegisterActorState::Server(r) => {
+                        matches!(r.status, Status::Normal { .. }) && !r.log.is_empty()
+                    }
+                    _ => false,
+                }
+            })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 317_010);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, ignore = "enabled for --release only")]
+    fn view_change_with_writes_in_flight() {
+        // Pruning every path on which the first primary commits an operation means that a write
+        // can only complete if a view change carries it over to the next primary.
+        let checker = base_model(1)
+            .within_boundary(|max_view, state| {
+                within_views(max_view, state)
+                    && replicas(state).next().is_none_or(|r| r.view > 0 || r.commit == 0)
+            })
+            .actors(vr_actors(3, None))
+            .actor(RegisterActor::Client { put_count: 1, server_count: 3 })
+            .property(Expectation::Sometimes, "write survives view change", |_, state| {
+                let prepared_in_first_view = state.network.iter_deliverable().any(|e| {
+                    matches!(e.msg, RegisterMsg::Internal(Prepare {
+                        view: 0, command: Command::Put(..), ..
+                    }))
+                });
+                let acknowledged = state.network.iter_deliverable().any(|e| {
+                    matches!(e.msg, RegisterMsg::PutOk(_))
+                });
+                prepared_in_first_view && acknowledged
+            })
+            .checker().threads(num_cpus::get()).spawn_dfs().join();
+        checker.assert_properties();
+        assert_eq!(checker.unique_state_count(), 595_940);
+    }
+
+    /// Replicas that take turns as primary in order of their IDs, optionally including one that is
+    /// recovering.
+    fn vr_actors(count: usize, recovering: Option<Id>) -> Vec<RegisterActor<VrActor>> {
+        Id::vec_from(0..count).into_iter()
+            .map(|id| RegisterActor::Server(VrActor {
+                replicas: Id::vec_from(0..count),
+                recovery_nonce: if Some(id) == recovering { Some(1) } else { None },
+            }))
+            .collect()
+    }
+
+    fn replicas(state: &ActorModelState<RegisterActor<VrActor>,
+                                         LinearizabilityTester<Id, Register<char>>>)
+        -> impl Iterator<Item = &VrState>
+    {
+        state.actor_states.iter().filter_map(|s| match &**s {
+            RegisterActorState::Server(s) => Some(s),
+            _ => None,
+        })
+    }
+
+    fn within_views(max_view: &View,
+                    state: &ActorModelState<RegisterActor<VrActor>,
+                                            LinearizabilityTester<Id, Register<char>>>)
+        -> bool
+    {
+        replicas(state).all(|r| r.view <= *max_view)
+    }
+
+    /// View changes can repeat indefinitely, so the model stops exploring after `max_view`.
+    fn base_model(max_view: View)
+        -> ActorModel<
+            RegisterActor<VrActor>,
+            View,
+            LinearizabilityTester<Id, Register<char>>>
+    {
+        ActorModel::new(
+                max_view,
+                LinearizabilityTester::new(Register('?'))
+            )
+            .within_boundary(within_views)
+            .property(Expectation::Always, "linearizable", |_, state| {
+                state.history.serialized_history().is_some()
+            })
+            .property(Expectation::Sometimes, "value chosen", |_, state| {
+                state.network.iter_deliverable().any(|e| {
+                    if let RegisterMsg::GetOk(_, value) = e.msg {
+                        return *value != '?';
+                    }
+                    false
+                })
+            })
+            .record_msg_in(RegisterMsg::record_returns)
+            .record_msg_out(RegisterMsg::record_invocations)
+    }
+    // ANCHOR_END: test
+}
+/* ANCHOR_END: all */